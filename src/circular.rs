@@ -5,6 +5,13 @@ use core::iter;
 
 use core::ptr;
 
+#[cfg(feature = "serde")]
+use serde::{
+    de::{SeqAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
 pub struct Circular<T> {
     head: *mut Node<T>,
     tail: *mut Node<T>,
@@ -66,13 +73,13 @@ impl<T> Circular<T> {
     /// remove something from tail
     pub fn pop(&mut self) -> Option<T> {
         if self.tail.is_null() {
-            return None;
+            None
         } else if self.tail == self.head {
             unsafe {
                 let raw = self.tail;
                 self.tail = ptr::null_mut();
                 self.head = ptr::null_mut();
-                return Some(Box::from_raw(raw).value);
+                Some(Box::from_raw(raw).value)
             }
         } else {
             unsafe {
@@ -82,7 +89,7 @@ impl<T> Circular<T> {
                 (*(*raw).next).prev = (*raw).prev;
                 (*raw).next = ptr::null_mut();
                 (*raw).prev = ptr::null_mut();
-                return Some(Box::from_raw(raw).value);
+                Some(Box::from_raw(raw).value)
             }
         }
     }
@@ -116,13 +123,13 @@ impl<T> Circular<T> {
     /// remove something from head
     pub fn shift(&mut self) -> Option<T> {
         if self.tail.is_null() {
-            return None;
+            None
         } else if self.tail == self.head {
             unsafe {
                 let raw = self.head;
                 self.tail = ptr::null_mut();
                 self.head = ptr::null_mut();
-                return Some(Box::from_raw(raw).value);
+                Some(Box::from_raw(raw).value)
             }
         } else {
             unsafe {
@@ -132,7 +139,7 @@ impl<T> Circular<T> {
                 (*(*raw).next).prev = (*raw).prev;
                 (*raw).next = ptr::null_mut();
                 (*raw).prev = ptr::null_mut();
-                return Some(Box::from_raw(raw).value);
+                Some(Box::from_raw(raw).value)
             }
         }
     }
@@ -142,9 +149,7 @@ impl<T> Circular<T> {
         if self.head.is_null() {
             return None;
         }
-        unsafe {
-            return Some(&(*self.head).value);
-        }
+        unsafe { Some(&(*self.head).value) }
     }
 
     /// peeks the front element as a mutable reference
@@ -152,9 +157,7 @@ impl<T> Circular<T> {
         if self.head.is_null() {
             return None;
         }
-        unsafe {
-            return Some(&mut (*self.head).value);
-        }
+        unsafe { Some(&mut (*self.head).value) }
     }
 
     /// peeks the rear element as a shared reference
@@ -162,9 +165,7 @@ impl<T> Circular<T> {
         if self.tail.is_null() {
             return None;
         }
-        unsafe {
-            return Some(&mut (*self.tail).value);
-        }
+        unsafe { Some(&(*self.tail).value) }
     }
 
     /// peeks the rear element as a mutable reference
@@ -172,16 +173,10 @@ impl<T> Circular<T> {
         if self.tail.is_null() {
             return None;
         }
-        unsafe {
-            return Some(&mut (*self.tail).value);
-        }
+        unsafe { Some(&mut (*self.tail).value) }
     }
 
-    pub fn into_iter(self) -> MovedIteration<T> {
-        MovedIteration(self)
-    }
-
-    pub fn iter(&self) -> Iteration<T> {
+    pub fn iter(&self) -> Iteration<'_, T> {
         unsafe {
             Iteration {
                 first: self.head.as_ref(),
@@ -190,7 +185,7 @@ impl<T> Circular<T> {
         }
     }
 
-    pub fn iter_mut(&self) -> MutableIteration<T> {
+    pub fn iter_mut(&self) -> MutableIteration<'_, T> {
         unsafe {
             MutableIteration {
                 first: self.head.as_mut(),
@@ -200,6 +195,21 @@ impl<T> Circular<T> {
     }
 }
 
+impl<T> Default for Circular<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IntoIterator for Circular<T> {
+    type Item = T;
+    type IntoIter = MovedIteration<T>;
+
+    fn into_iter(self) -> MovedIteration<T> {
+        MovedIteration(self)
+    }
+}
+
 impl<E> FromIterator<E> for Circular<E> {
     fn from_iter<T: IntoIterator<Item = E>>(iter: T) -> Self {
         let mut instance = Self::new();
@@ -213,7 +223,7 @@ impl<E> FromIterator<E> for Circular<E> {
 impl<T> Drop for Circular<T> {
     fn drop(&mut self) {
         // lazy drop: recursively move things out of scope and drop them.
-        while let Some(_) = self.pop() {}
+        while self.pop().is_some() {}
     }
 }
 
@@ -305,11 +315,50 @@ impl<T> iter::DoubleEndedIterator for MovedIteration<T> {
 
 impl<T> iter::FusedIterator for MovedIteration<T> {}
 
+/// serializes as a sequence, head to tail
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for Circular<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // some formats need the length up front
+        let mut seq = serializer.serialize_seq(Some(self.iter().count()))?;
+        for thing in self.iter() {
+            seq.serialize_element(thing)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Circular<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CircularVisitor<T>(core::marker::PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for CircularVisitor<T> {
+            type Value = Circular<T>;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("a sequence")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut instance = Circular::new();
+                while let Some(thing) = seq.next_element()? {
+                    instance.push(thing);
+                }
+                Ok(instance)
+            }
+        }
+
+        deserializer.deserialize_seq(CircularVisitor(core::marker::PhantomData))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::clone_on_copy)]
     fn reads() {
         let a = vec![1, 3, 5, 7, 9, 2, 4, 6, 8, 10, 11, 12, 13, 14, 15];
         let mut b = Circular::new();
@@ -342,4 +391,23 @@ mod tests {
         e.into_iter().rev().for_each(|x| f.push(*x));
         assert_eq!(a_rev, f);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut a: Circular<String> = Circular::new();
+        a.push("b".to_owned());
+        a.push("c".to_owned());
+        a.unshift("a".to_owned());
+        let json = serde_json::to_string(&a).unwrap();
+        assert_eq!(json, r#"["a","b","c"]"#);
+        let b: Circular<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(b.iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        let empty: Circular<u8> = serde_json::from_str("[]").unwrap();
+        assert!(empty.front().is_none());
+        let c: Circular<u16> = (1..=5).collect();
+        let encoded = bincode::serialize(&c).unwrap();
+        let d: Circular<u16> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(d.into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
 }
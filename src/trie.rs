@@ -1,75 +1,346 @@
-use std::ops::AddAssign;
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::iter::FusedIterator;
+use std::ops::Index;
 
-#[derive(Debug, Clone)]
-pub struct KeyExistsError;
+#[cfg(feature = "serde")]
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
-#[derive(Debug, Clone)]
-pub struct KeyNotFoundError;
+mod automaton;
+mod children;
+mod concurrent;
+mod frozen;
+mod fuzzy;
+mod glob;
+mod merge;
+mod normalize;
+mod persistent;
+mod range;
+mod rank;
+mod stats;
+mod subtrie;
+mod suffix;
+#[cfg(test)]
+mod testing;
+mod validate;
+
+pub use automaton::{Automaton, MatchKind, Occurrences, StreamOccurrences};
+use children::Children;
+pub use concurrent::ConcurrentTrie;
+pub use frozen::{FrozenIteration, FrozenTrie, FrozenTrieError};
+pub use fuzzy::Fuzzy;
+pub use glob::Matches;
+pub use normalize::{Case, Normalization, NormalizedTrie, Spellings, UnicodeForm};
+pub use persistent::{PersistentIteration, PersistentTrie};
+pub use range::Range;
+pub use stats::TrieStats;
+pub use subtrie::{SubIteration, SubIterationMut, SubTrie, SubTrieMut};
+pub use suffix::SuffixTrie;
+pub use validate::InvariantError;
+
+/// a key that already holds a value, with the value that was not inserted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyExistsError<V, K = String> {
+    pub key: K,
+    pub value: V,
+}
+
+/// a key that holds no value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyNotFoundError<K = String> {
+    pub key: K,
+}
+
+impl<V, K: Debug> Display for KeyExistsError<V, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {:?} already exists", self.key)
+    }
+}
+
+impl<K: Debug> Display for KeyNotFoundError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {:?} not found", self.key)
+    }
+}
+
+impl<V: Debug, K: Debug> Error for KeyExistsError<V, K> {}
+
+impl<K: Debug> Error for KeyNotFoundError<K> {}
+
+/// single element of a key
+///
+/// atoms with a radix byte let wide nodes index their children by table
+/// instead of searching them.
+pub trait TrieAtom: Clone + Ord + Debug {
+    /// the atom as a byte, if every value of the type has one
+    ///
+    /// the radix must keep the order of atoms.
+    #[inline]
+    fn radix(&self) -> Option<u8> {
+        None
+    }
+}
+
+impl TrieAtom for u8 {
+    #[inline]
+    fn radix(&self) -> Option<u8> {
+        Some(*self)
+    }
+}
+
+impl TrieAtom for i8 {
+    #[inline]
+    fn radix(&self) -> Option<u8> {
+        // flip the sign bit so negatives order first
+        Some(*self as u8 ^ 0x80)
+    }
+}
+
+impl TrieAtom for bool {
+    #[inline]
+    fn radix(&self) -> Option<u8> {
+        Some(*self as u8)
+    }
+}
+
+macro_rules! searched_atoms {
+    ($($atom:ty),*) => {
+        $(impl TrieAtom for $atom {})*
+    };
+}
+
+searched_atoms!(u16, u32, u64, u128, usize, i16, i32, i64, i128, isize, char, String);
+
+/// a key type a trie can be built over
+///
+/// keys are sequences of atoms, the trie compresses common runs of atoms.
+/// lookups take the borrowed form of the key, like `&str` for `String`.
+///
+/// nodes only split keys between units, so for `String` every node holds
+/// whole characters, and siblings part on their first character even where
+/// characters share a leading byte.
+pub trait TrieKey: Sized + Borrow<Self::Slice> {
+    /// single element of a key
+    type Atom: TrieAtom;
+    /// borrowed form of the key
+    type Slice: ?Sized + ToOwned<Owned = Self>;
+
+    /// views a key as its atoms
+    fn atoms(key: &Self::Slice) -> &[Self::Atom];
+
+    /// the first len atoms of a key
+    ///
+    /// len is the length of a stored key the key starts with.
+    fn slice_prefix(key: &Self::Slice, len: usize) -> &Self::Slice;
+
+    /// rebuilds a key from all of its atoms
+    fn from_atoms(atoms: &[Self::Atom]) -> Self;
+
+    /// number of atoms in the unit starting with first, like the bytes of
+    /// a character, which nodes are never split within and edit distances
+    /// count as one
+    #[inline]
+    fn unit_len(_first: &Self::Atom) -> usize {
+        1
+    }
+}
+
+/// string keys, compressed bytewise
+impl TrieKey for String {
+    type Atom = u8;
+    type Slice = str;
+
+    #[inline]
+    fn atoms(key: &str) -> &[u8] {
+        key.as_bytes()
+    }
+
+    #[inline]
+    fn slice_prefix(key: &str, len: usize) -> &str {
+        &key[..len]
+    }
+
+    fn from_atoms(atoms: &[u8]) -> Self {
+        // only ever called with whole keys, which came in as valid strings
+        String::from_utf8(atoms.to_vec()).expect("trie keys are valid utf-8")
+    }
+
+    #[inline]
+    fn unit_len(first: &u8) -> usize {
+        // utf-8 lead bytes tell the length of their character
+        match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        }
+    }
+}
+
+/// sequence keys, like bytes, token ids, path segments or bits
+impl<T: TrieAtom> TrieKey for Vec<T> {
+    type Atom = T;
+    type Slice = [T];
+
+    #[inline]
+    fn atoms(key: &[T]) -> &[T] {
+        key
+    }
+
+    #[inline]
+    fn slice_prefix(key: &[T], len: usize) -> &[T] {
+        &key[..len]
+    }
+
+    fn from_atoms(atoms: &[T]) -> Self {
+        atoms.to_vec()
+    }
+}
 
 /// compressed prefix tree
 ///
-/// holds arbitrary values, uses string keys
+/// holds arbitrary values, keyed by anything implementing [`TrieKey`].
 /// common slices of stored keys are compressed by
 /// not storing duplicates of those common slices.
+///
+/// keys are strings unless another key type is named, `Trie<V, Vec<u8>>`
+/// is built with [`Trie::empty`].
 #[derive(Debug)]
-pub struct Trie<V> {
+pub struct Trie<V, K: TrieKey = String> {
     /// tree root
-    /// this will always be a node with the empty string.
-    root: TrieNode<V>,
+    /// this will always be a node with the empty prefix.
+    root: TrieNode<K::Atom, V>,
+    /// number of keys
+    len: usize,
 }
 
-#[derive(Debug)]
-struct TrieNode<V> {
-    prefix: String,
-    children: Vec<TrieNode<V>>,
+#[derive(Debug, Clone)]
+struct TrieNode<A, V> {
+    prefix: Vec<A>,
+    children: Children<A, V>,
     value: Option<V>,
 }
 
 impl<V> Trie<V> {
-    /// constructs an empty prefix tree
+    /// constructs an empty prefix tree with string keys
     pub fn new() -> Self {
+        Trie::empty()
+    }
+}
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// constructs an empty prefix tree, for any key type
+    pub fn empty() -> Self {
         Trie {
             root: TrieNode {
                 value: None,
-                prefix: "".to_owned(),
-                children: Vec::new(),
+                prefix: Vec::new(),
+                children: Children::new(),
             },
+            len: 0,
         }
     }
 
     /// gets the value of a key
     #[inline]
-    fn get(&self, key: &str) -> Option<&V> {
-        self.root.get(key)
+    pub fn get(&self, key: &K::Slice) -> Option<&V> {
+        self.root.get(K::atoms(key))
     }
 
     /// gets the value of a key as mutable
     #[inline]
-    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
-        self.root.get_mut(key)
+    pub fn get_mut(&mut self, key: &K::Slice) -> Option<&mut V> {
+        self.root.get_mut(K::atoms(key))
     }
 
     /// checks if a key exists
     #[inline]
-    pub fn has(&self, key: &str) -> bool {
+    pub fn has(&self, key: &K::Slice) -> bool {
         self.get(key).is_some()
     }
 
     /// sets a key to a value
     /// returns the key evicted if there was already a key.
     #[inline]
-    pub fn set(&mut self, key: &str, val: V) -> Option<V> {
-        self.root.insert(key, val)
+    pub fn set(&mut self, key: &K::Slice, val: V) -> Option<V> {
+        let old = self.root.insert::<K>(K::atoms(key), val);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
     }
 
     /// removes a key
     ///
     /// Ok() if key existed, Err() otherwise
     #[inline]
-    pub fn remove(&mut self, key: &str) -> Result<V, KeyNotFoundError> {
-        match self.root.remove(key) {
-            None => Err(KeyNotFoundError),
-            Some(data) => Ok(data),
+    pub fn remove(&mut self, key: &K::Slice) -> Result<V, KeyNotFoundError<K>> {
+        match self.root.remove(K::atoms(key)) {
+            None => Err(KeyNotFoundError {
+                key: key.to_owned(),
+            }),
+            Some(data) => {
+                self.len -= 1;
+                Ok(data)
+            }
+        }
+    }
+
+    /// removes every key starting with prefix, returns them as a trie of their own
+    ///
+    /// the subtree is detached whole, only counting its keys walks it.
+    pub fn remove_prefix(&mut self, prefix: &K::Slice) -> Self {
+        let atoms = K::atoms(prefix);
+        if atoms.is_empty() {
+            return std::mem::take(self);
+        }
+        let mut removed = Trie::empty();
+        if let Some((depth, mut node)) = self.root.remove_prefix_below(atoms) {
+            // the detached node carries the whole key leading up to it
+            node.prefix.splice(..0, atoms[..depth].iter().cloned());
+            removed.len = node.count();
+            removed.root.children.insert(node);
+        }
+        self.len -= removed.len;
+        removed
+    }
+
+    /// removes every key starting with prefix, iterates over them in key order
+    pub fn drain_prefix(&mut self, prefix: &K::Slice) -> MovedIteration<K, V> {
+        self.remove_prefix(prefix).into_iter()
+    }
+
+    /// sets a key to a value, unless the key already has one
+    ///
+    /// Ok() with the inserted value, Err() with the key and the rejected value otherwise
+    pub fn try_insert(&mut self, key: &K::Slice, val: V) -> Result<&mut V, KeyExistsError<V, K>> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err(KeyExistsError {
+                key: entry.key,
+                value: val,
+            }),
+            Entry::Vacant(entry) => Ok(entry.insert(val)),
+        }
+    }
+
+    /// gets the entry of a key, for in place manipulation
+    pub fn entry(&mut self, key: &K::Slice) -> Entry<'_, K, V> {
+        let key = key.to_owned();
+        let len = &mut self.len;
+        match self.root.seek(K::atoms(key.borrow()), 0) {
+            Spot::Occupied(parent, at) => Entry::Occupied(OccupiedEntry {
+                key,
+                parent,
+                at,
+                len,
+            }),
+            Spot::Vacant(node, at) => Entry::Vacant(VacantEntry { key, node, at, len }),
         }
     }
 
@@ -78,18 +349,543 @@ impl<V> Trie<V> {
     pub fn size(&self) -> usize {
         self.root.size()
     }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// iterates over key/value pairs in lexicographic key order
+    pub fn iter(&self) -> Iteration<'_, K, V> {
+        Iteration {
+            stack: vec![(0, &self.root)],
+            key: Vec::new(),
+        }
+    }
+
+    /// iterates over key/value pairs in lexicographic key order,
+    /// with values as mutable
+    pub fn iter_mut(&mut self) -> MutableIteration<'_, K, V> {
+        MutableIteration {
+            stack: vec![(0, &mut self.root)],
+            key: Vec::new(),
+        }
+    }
+
+    /// iterates over key/value pairs whose key starts with prefix,
+    /// in lexicographic key order
+    pub fn iter_prefix(&self, prefix: &K::Slice) -> Iteration<'_, K, V> {
+        let prefix = K::atoms(prefix);
+        match self.root.cover(prefix) {
+            None => Iteration {
+                stack: Vec::new(),
+                key: Vec::new(),
+            },
+            Some((depth, node)) => Iteration {
+                stack: vec![(depth, node)],
+                key: prefix[..depth].to_vec(),
+            },
+        }
+    }
+
+    /// iterates over key/value pairs whose key starts with prefix,
+    /// in lexicographic key order, with values as mutable
+    pub fn iter_prefix_mut(&mut self, prefix: &K::Slice) -> MutableIteration<'_, K, V> {
+        let prefix = K::atoms(prefix);
+        match self.root.cover_mut(prefix) {
+            None => MutableIteration {
+                stack: Vec::new(),
+                key: Vec::new(),
+            },
+            Some((depth, node)) => MutableIteration {
+                stack: vec![(depth, node)],
+                key: prefix[..depth].to_vec(),
+            },
+        }
+    }
+
+    /// finds the longest stored key that is a prefix of key
+    ///
+    /// returns that part of key and its value.
+    #[inline]
+    pub fn longest_prefix_match<'k>(&self, key: &'k K::Slice) -> Option<(&'k K::Slice, &V)> {
+        self.prefixes_of(key).last()
+    }
+
+    /// iterates over stored keys that are prefixes of key, shortest to longest
+    pub fn prefixes_of<'k>(&self, key: &'k K::Slice) -> Prefixes<'_, 'k, K, V> {
+        Prefixes {
+            next: Some((0, &self.root)),
+            key,
+        }
+    }
+
+    /// iterates over keys in lexicographic order
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    /// iterates over values in lexicographic key order
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            stack: vec![&self.root],
+        }
+    }
+}
+
+/// key ordered iteration over a trie
+///
+/// walks the tree depth first, children are kept sorted so this is lexicographic.
+pub struct Iteration<'a, K: TrieKey, V> {
+    /// nodes left to visit, with the length of the key leading up to them
+    stack: Vec<(usize, &'a TrieNode<K::Atom, V>)>,
+    /// key of the last node visited
+    key: Vec<K::Atom>,
+}
+
+/// key ordered iteration over a trie, with values as mutable
+pub struct MutableIteration<'a, K: TrieKey, V> {
+    stack: Vec<(usize, &'a mut TrieNode<K::Atom, V>)>,
+    key: Vec<K::Atom>,
+}
+
+/// key ordered iteration over a trie, moving out of it
+pub struct MovedIteration<K: TrieKey, V> {
+    stack: Vec<(usize, TrieNode<K::Atom, V>)>,
+    key: Vec<K::Atom>,
+}
+
+/// iteration over the stored prefixes of a key, shortest to longest
+pub struct Prefixes<'a, 'k, K: TrieKey, V> {
+    /// next node on the path of the key,
+    /// with the length of the key up to and including it
+    next: Option<(usize, &'a TrieNode<K::Atom, V>)>,
+    key: &'k K::Slice,
+}
+
+/// key ordered iteration over the keys of a trie
+pub struct Keys<'a, K: TrieKey, V>(Iteration<'a, K, V>);
+
+/// key ordered iteration over the values of a trie
+pub struct Values<'a, K: TrieKey, V> {
+    stack: Vec<&'a TrieNode<K::Atom, V>>,
+}
+
+impl<'a, K: TrieKey, V> Iterator for Iteration<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            // drop the key of the previous branch, append ours
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.prefix);
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.iter().rev().map(|child| (depth, child)));
+            if let Some(value) = node.value.as_ref() {
+                return Some((K::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K: TrieKey, V> Iterator for MutableIteration<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            // split the borrow so children and value can be handed out separately
+            let TrieNode {
+                prefix,
+                children,
+                value,
+            } = node;
+            self.key.truncate(depth);
+            self.key.extend_from_slice(prefix);
+            let depth = self.key.len();
+            self.stack
+                .extend(children.iter_mut().rev().map(|child| (depth, child)));
+            if let Some(value) = value.as_mut() {
+                return Some((K::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<K: TrieKey, V> Iterator for MovedIteration<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.prefix);
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.into_iter().rev().map(|child| (depth, child)));
+            if let Some(value) = node.value {
+                return Some((K::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, 'k, K: TrieKey, V> Iterator for Prefixes<'a, 'k, K, V> {
+    type Item = (&'k K::Slice, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.next.take() {
+            // same descent as get, just stopping at every value on the way
+            let rest = &K::atoms(self.key)[depth..];
+            self.next = node
+                .leaf(rest)
+                .map(|leaf| (depth + leaf.prefix.len(), leaf));
+            if let Some(value) = node.value.as_ref() {
+                return Some((K::slice_prefix(self.key, depth), value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K: TrieKey, V> Iterator for Keys<'a, K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: TrieKey, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            self.stack.extend(node.children.iter().rev());
+            if let Some(value) = node.value.as_ref() {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+impl<K: TrieKey, V> FusedIterator for Iteration<'_, K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for MutableIteration<'_, K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for MovedIteration<K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for Prefixes<'_, '_, K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for Keys<'_, K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for Values<'_, K, V> {}
+
+impl<K: TrieKey, V> IntoIterator for Trie<V, K> {
+    type Item = (K, V);
+    type IntoIter = MovedIteration<K, V>;
+
+    fn into_iter(self) -> MovedIteration<K, V> {
+        MovedIteration {
+            stack: vec![(0, self.root)],
+            key: Vec::new(),
+        }
+    }
+}
+
+impl<'a, K: TrieKey, V> IntoIterator for &'a Trie<V, K> {
+    type Item = (K, &'a V);
+    type IntoIter = Iteration<'a, K, V>;
+
+    fn into_iter(self) -> Iteration<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: TrieKey, V> IntoIterator for &'a mut Trie<V, K> {
+    type Item = (K, &'a mut V);
+    type IntoIter = MutableIteration<'a, K, V>;
+
+    fn into_iter(self) -> MutableIteration<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K: TrieKey, V> Default for Trie<V, K> {
+    fn default() -> Self {
+        Trie::empty()
+    }
+}
+
+impl<K: TrieKey, V: Clone> Clone for Trie<V, K> {
+    fn clone(&self) -> Self {
+        Trie {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+/// tries are equal when they hold the same keys and values.
+/// a set of keys has only one compressed shape, so this compares nodes.
+impl<K: TrieKey, V: PartialEq> PartialEq for Trie<V, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.root == other.root
+    }
+}
+
+impl<K: TrieKey, V: Eq> Eq for Trie<V, K> {}
+
+impl<K: TrieKey, V: Hash> Hash for Trie<V, K>
+where
+    K::Atom: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.root.hash(state);
+    }
 }
 
-impl<V> TrieNode<V> {
+impl<K: TrieKey, V> FromIterator<(K, V)> for Trie<V, K> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut trie = Trie::empty();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K: TrieKey, V> Extend<(K, V)> for Trie<V, K> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.set(key.borrow(), value);
+        }
+    }
+}
+
+/// gets the value of a key, panics if it does not exist
+impl<K: TrieKey, V> Index<&K::Slice> for Trie<V, K> {
+    type Output = V;
+
+    fn index(&self, key: &K::Slice) -> &V {
+        self.get(key).expect("key not found in trie")
+    }
+}
+
+/// compares nodes by content, the child layouts may differ
+impl<A: TrieAtom, V: PartialEq> PartialEq for TrieNode<A, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefix == other.prefix
+            && self.value == other.value
+            && self.children.len() == other.children.len()
+            && self.children.iter().eq(other.children.iter())
+    }
+}
+
+impl<A: TrieAtom + Hash, V: Hash> Hash for TrieNode<A, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.prefix.hash(state);
+        self.value.hash(state);
+        self.children.len().hash(state);
+        for child in self.children.iter() {
+            child.hash(state);
+        }
+    }
+}
+
+/// a single key of a trie, either holding a value or not
+pub enum Entry<'a, K: TrieKey, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// entry of a key that holds a value
+///
+/// holds on to the parent of the node with the value, so removing
+/// the key can compact the tree around it.
+pub struct OccupiedEntry<'a, K: TrieKey, V> {
+    key: K,
+    /// the root, when the key is empty
+    parent: &'a mut TrieNode<K::Atom, V>,
+    /// length of the key leading up to the node with the value
+    at: usize,
+    len: &'a mut usize,
+}
+
+/// entry of a key that holds no value
+///
+/// holds on to the node the key ends in or branches off below.
+pub struct VacantEntry<'a, K: TrieKey, V> {
+    key: K,
+    node: &'a mut TrieNode<K::Atom, V>,
+    /// length of the key up to and including the node's prefix
+    at: usize,
+    len: &'a mut usize,
+}
+
+/// where a key was found by a single descent
+enum Spot<'a, A, V> {
+    /// parent of the node holding the value, and the length of the key
+    /// leading up to that node
+    Occupied(&'a mut TrieNode<A, V>, usize),
+    /// node the key ends in or branches off below, and the length of the
+    /// key up to and including its prefix
+    Vacant(&'a mut TrieNode<A, V>, usize),
+}
+
+impl<'a, K: TrieKey, V> Entry<'a, K, V> {
+    /// gets the key of the entry
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// inserts a value if vacant, returns the value of the key
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// inserts a computed value if vacant, returns the value of the key
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// inserts a value computed from the key if vacant, returns the value of the key
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// modifies the value if occupied
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: TrieKey, V: Default> Entry<'a, K, V> {
+    /// inserts the default value if vacant, returns the value of the key
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: TrieKey, V> OccupiedEntry<'a, K, V> {
+    /// gets the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// gets the value
+    pub fn get(&self) -> &V {
+        let rest = &K::atoms(self.key.borrow())[self.at..];
+        match rest.is_empty() {
+            true => self.parent.value.as_ref().unwrap(),
+            false => self.parent.leaf(rest).unwrap().value.as_ref().unwrap(),
+        }
+    }
+
+    /// gets the value as mutable
+    pub fn get_mut(&mut self) -> &mut V {
+        let rest = &K::atoms(self.key.borrow())[self.at..];
+        match rest.is_empty() {
+            true => self.parent.value.as_mut().unwrap(),
+            false => self.parent.leaf_mut(rest).unwrap().value.as_mut().unwrap(),
+        }
+    }
+
+    /// converts into the value, bound to the trie's lifetime
+    pub fn into_mut(self) -> &'a mut V {
+        let rest = &K::atoms(self.key.borrow())[self.at..];
+        match rest.is_empty() {
+            true => self.parent.value.as_mut().unwrap(),
+            false => self.parent.leaf_mut(rest).unwrap().value.as_mut().unwrap(),
+        }
+    }
+
+    /// sets the value, returns the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// removes the key from the trie, returns its value
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// removes the key from the trie, returns it with its value
+    pub fn remove_entry(self) -> (K, V) {
+        *self.len -= 1;
+        let rest = &K::atoms(self.key.borrow())[self.at..];
+        if rest.is_empty() {
+            let value = self.parent.value.take().unwrap();
+            return (self.key, value);
+        }
+        let value = self.parent.leaf_mut(rest).unwrap().value.take().unwrap();
+        self.parent.compact_child(rest);
+        // the parent may have lost a child, fold in what remains the way
+        // its own parent would. the root, at 0, keeps its empty prefix.
+        let parent = &mut *self.parent;
+        if self.at != 0 && parent.value.is_none() && parent.children.len() == 1 {
+            parent.take_only_child();
+        }
+        (self.key, value)
+    }
+}
+
+impl<'a, K: TrieKey, V> VacantEntry<'a, K, V> {
+    /// gets the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// takes the key out of the entry
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// sets the value, returns it as mutable
+    pub fn insert(self, value: V) -> &'a mut V {
+        *self.len += 1;
+        let rest = &K::atoms(self.key.borrow())[self.at..];
+        self.node.slot_below::<K>(rest).insert(value)
+    }
+}
+
+impl<A: TrieAtom, V> TrieNode<A, V> {
     fn size(&self) -> usize {
         let mut size = 1;
         for other in self.children.iter() {
             size += other.size();
         }
-        return size;
+        size
     }
 
-    fn get(&self, key: &str) -> Option<&V> {
+    fn get(&self, key: &[A]) -> Option<&V> {
         if key == self.prefix {
             return self.value.as_ref();
         }
@@ -101,16 +897,36 @@ impl<V> TrieNode<V> {
         }
     }
 
-    fn leaf(&self, key: &str) -> Option<&Self> {
-        for node in self.children.iter() {
-            if key.starts_with(&node.prefix) {
-                return Some(&node);
-            }
+    /// gets the child key continues into
+    fn leaf(&self, key: &[A]) -> Option<&Self> {
+        self.children
+            .get(key)
+            .filter(|node| key.starts_with(&node.prefix))
+    }
+
+    /// finds the topmost node whose keys all start with key
+    ///
+    /// the node's own prefix may run past the end of key.
+    /// returns the length of the key leading up to that node, and the node.
+    fn cover(&self, key: &[A]) -> Option<(usize, &Self)> {
+        if self.prefix.starts_with(key) {
+            return Some((0, self));
         }
-        None
+        let rest = &key[self.prefix.len()..];
+        let (depth, node) = self.children.get(rest)?.cover(rest)?;
+        Some((depth + self.prefix.len(), node))
     }
 
-    fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+    fn cover_mut(&mut self, key: &[A]) -> Option<(usize, &mut Self)> {
+        if self.prefix.starts_with(key) {
+            return Some((0, self));
+        }
+        let rest = &key[self.prefix.len()..];
+        let (depth, node) = self.children.get_mut(rest)?.cover_mut(rest)?;
+        Some((depth + self.prefix.len(), node))
+    }
+
+    fn get_mut(&mut self, key: &[A]) -> Option<&mut V> {
         if key == self.prefix {
             return self.value.as_mut();
         }
@@ -122,136 +938,234 @@ impl<V> TrieNode<V> {
         }
     }
 
-    fn leaf_mut(&mut self, key: &str) -> Option<&mut Self> {
-        for node in self.children.iter_mut() {
-            if key.starts_with(&node.prefix) {
-                return Some(node);
-            }
+    fn leaf_mut(&mut self, key: &[A]) -> Option<&mut Self> {
+        self.children
+            .get_mut(key)
+            .filter(|node| key.starts_with(&node.prefix))
+    }
+
+    fn insert<K: TrieKey<Atom = A>>(&mut self, key: &[A], value: V) -> Option<V> {
+        self.slot::<K>(key).replace(value)
+    }
+
+    /// gets the value slot of a key, creating nodes for it as needed
+    ///
+    /// a created node is valueless until the caller fills the slot.
+    fn slot<K: TrieKey<Atom = A>>(&mut self, key: &[A]) -> &mut Option<V> {
+        self.slot_below::<K>(&key[self.prefix.len()..])
+    }
+
+    /// descends once along key, from at on, key includes our prefix
+    fn seek(&mut self, key: &[A], at: usize) -> Spot<'_, A, V> {
+        let rest = &key[at..];
+        if rest.is_empty() {
+            return match self.value.is_some() {
+                true => Spot::Occupied(self, at),
+                false => Spot::Vacant(self, at),
+            };
         }
-        None
+        let depth = match self.leaf(rest) {
+            Some(leaf) => leaf.prefix.len(),
+            None => return Spot::Vacant(self, at),
+        };
+        if depth == rest.len() {
+            return match self.leaf(rest).unwrap().value.is_some() {
+                true => Spot::Occupied(self, at),
+                false => Spot::Vacant(self, at),
+            };
+        }
+        self.leaf_mut(rest).unwrap().seek(key, at + depth)
     }
 
-    fn insert(&mut self, key: &str, value: V) -> Option<V> {
-        if key == self.prefix {
-            return self.value.replace(value);
+    /// gets the value slot of our prefix followed by rest
+    fn slot_below<K: TrieKey<Atom = A>>(&mut self, rest: &[A]) -> &mut Option<V> {
+        if rest.is_empty() {
+            return &mut self.value;
         }
-        let rest = &key[self.prefix.len()..];
-        let leaf = self.leaf_mut(rest);
         // still longer than leaf, and leaf exists
-        if leaf.is_some() {
-            return leaf.unwrap().insert(rest, value);
-        }
-        // shorter than a valid leaf split target
-        let split = self.insert_split_target(rest);
-        if split.is_some() {
-            let (idx, node) = split.unwrap();
-            let inject = TrieNode {
-                prefix: (&rest[(rest.len() - 1)..(node.prefix.len() - rest.len())]).to_owned(),
-                children: Vec::new(),
-                value: Some(value),
-            };
-            let moved = std::mem::replace(&mut self.children[idx], inject);
-            self.children[idx].children.push(moved);
-            return None;
+        if self.leaf(rest).is_some() {
+            return self.leaf_mut(rest).unwrap().slot::<K>(rest);
+        }
+        // shares the first unit of a leaf's prefix, split the leaf where
+        // the last whole unit of the common part ends
+        let unit = first_unit::<K>(rest);
+        if self.children.get(unit).is_some() {
+            let split = self.children.get_mut(unit).unwrap();
+            let common = unit_floor::<K>(rest, common_prefix_len(&split.prefix, rest));
+            split.split(common);
+            if common == rest.len() {
+                return &mut split.value;
+            }
+            return split.inject(&rest[common..]);
         }
         // neither a leaf is our prefix, nor are we a leaf prefix, inject new leaf.
-        let inject = TrieNode {
-            prefix: rest.to_owned(),
-            children: Vec::new(),
-            value: Some(value),
+        self.inject(rest)
+    }
+
+    /// splits our prefix at, moving what follows into a new only child
+    ///
+    /// leaves us valueless, the caller gives us a value or a second child.
+    fn split(&mut self, at: usize) {
+        let moved = TrieNode {
+            prefix: self.prefix[at..].to_vec(),
+            children: std::mem::replace(&mut self.children, Children::new()),
+            value: self.value.take(),
         };
-        self.children.push(inject);
-        return None;
+        self.prefix.truncate(at);
+        self.children.insert(moved);
     }
 
-    fn insert_split_target(&mut self, key: &str) -> Option<(usize, &mut Self)> {
-        self.children
-            .iter_mut()
-            .enumerate()
-            .find(|(_idx, node)| node.prefix.starts_with(key))
+    /// injects a new valueless leaf
+    ///
+    /// returns the value slot of the leaf.
+    fn inject(&mut self, prefix: &[A]) -> &mut Option<V> {
+        let leaf = self.children.insert(TrieNode {
+            prefix: prefix.to_vec(),
+            children: Children::new(),
+            value: None,
+        });
+        &mut leaf.value
     }
-    
-    fn remove(&mut self, key: &str) -> Option<V> {
+
+    fn remove(&mut self, key: &[A]) -> Option<V> {
         if key == self.prefix {
             // us, this should only happen on first node. eject value.
             return self.value.take();
         }
-        self.remove_internal(&key[self.prefix.len()..])
+        self.remove_below(&key[self.prefix.len()..])
     }
 
-    fn remove_internal(&mut self, key: &str) -> Option<V> {
-        // get leaf node
-        let rest = &key[self.prefix.len()..];
-        let leaf = self.leaf_mut(rest);
-        if leaf.is_none() {
-            // not found, bail
-            return None;
-        }
-        // unwrap it - this relies on local variable shadowing
-        let leaf = leaf.unwrap();
-        // leaf is not exact
-        if leaf.prefix != rest {
-            // kick it down
-            return leaf.remove_internal(rest);
-        }
-        // leaf is exact
-        // evict value
-        let evicted = leaf.value.take();
-        // some options
-        match leaf.children.len() {
+    /// removes a key from below us, key excludes our prefix
+    ///
+    /// the child the key went through is compacted on the way back up.
+    fn remove_below(&mut self, key: &[A]) -> Option<V> {
+        let leaf = self.leaf_mut(key)?;
+        let evicted = if leaf.prefix == key {
+            leaf.value.take()?
+        } else {
+            let rest = &key[leaf.prefix.len()..];
+            leaf.remove_below(rest)?
+        };
+        self.compact_child(key);
+        Some(evicted)
+    }
+
+    /// detaches the topmost node below us whose keys all start with key,
+    /// key excludes our prefix
+    ///
+    /// returns it with the length of the key leading up to it.
+    /// the child the key went through is compacted on the way back up.
+    fn remove_prefix_below(&mut self, key: &[A]) -> Option<(usize, Self)> {
+        let child = self.children.get_mut(key)?;
+        if child.prefix.starts_with(key) {
+            return Some((0, self.children.remove(key).unwrap()));
+        }
+        let depth = child.prefix.len();
+        let (below, node) = child.remove_prefix_below(&key[depth..])?;
+        self.compact_child(key);
+        Some((depth + below, node))
+    }
+
+    /// counts the values at and below us
+    fn count(&self) -> usize {
+        usize::from(self.value.is_some())
+            + self
+                .children
+                .iter()
+                .map(|child| child.count())
+                .sum::<usize>()
+    }
+
+    /// restores compression of the child key went through,
+    /// after it lost a value or a subtree
+    ///
+    /// valueless children vanish if they have no children of their own,
+    /// and take their child in if they have one.
+    fn compact_child(&mut self, key: &[A]) {
+        let child = self.children.get_mut(key).unwrap();
+        if child.value.is_some() {
+            return;
+        }
+        match child.children.len() {
             0 => {
-                // empty, evict
-                let prefix = leaf.prefix.to_owned();
-                self.evict_node_with_prefix(&prefix);
-            }
-            1 => {
-                // 1 node. it should take the node below it.
-                leaf.take_below();
+                self.children.remove(key).unwrap();
             }
+            1 => child.take_only_child(),
             _ => {
-                // more than 1 node; do nothing, as it needs to stay there to be a split/branching node.
+                // needs to stay there to be a branching node
             }
         }
-        match self.children.len() {
-            1 => {
-                // we only have one child, we should take the node we just read
-                self.take_below();
+    }
+
+    /// folds our only child into us, we hold no value of our own
+    fn take_only_child(&mut self) {
+        let node = self.children.take_only();
+        self.prefix.extend(node.prefix);
+        self.children = node.children;
+        self.value = node.value;
+    }
+}
+
+/// serializes as a map of keys to values, in key order
+#[cfg(feature = "serde")]
+impl<K: TrieKey + Serialize, V: Serialize> Serialize for Trie<V, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // some formats need the length up front
+        let mut map = serializer.serialize_map(Some(self.len))?;
+        for (key, value) in self.iter() {
+            map.serialize_entry(&key, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K: TrieKey + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for Trie<V, K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TrieVisitor<K, V>(std::marker::PhantomData<(K, V)>);
+
+        impl<'de, K: TrieKey + Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for TrieVisitor<K, V> {
+            type Value = Trie<V, K>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map")
             }
-            _ => {
-                // can't do anything, we need to be a branching node
+
+            fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+                let mut trie = Trie::empty();
+                while let Some((key, value)) = map.next_entry::<K, V>()? {
+                    trie.set(key.borrow(), value);
+                }
+                Ok(trie)
             }
         }
-        // return evicted value
-        evicted
-    }
 
-    fn evict_node_with_prefix(&mut self, prefix: &str) {
-        self.children.swap_remove(
-            self.children
-                .iter()
-                .enumerate()
-                .find(|(_idx, n)| n.prefix == prefix)
-                .unwrap()
-                .0,
-        );
+        deserializer.deserialize_map(TrieVisitor(std::marker::PhantomData))
     }
+}
+
+/// length in atoms of the common start of two keys
+fn common_prefix_len<A: Eq>(a: &[A], b: &[A]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+/// the first unit of a non empty key
+fn first_unit<K: TrieKey>(key: &[K::Atom]) -> &[K::Atom] {
+    &key[..K::unit_len(&key[0]).min(key.len())]
+}
 
-    fn take_below(&mut self) {
-        // this only makes sense if we only have 1 node.
-        assert!(self.children.len() == 1);
-        // take the node from below
-        let taken = std::mem::replace(&mut self.children[0].children, Vec::new());
-        // remove the node we have
-        let node = self.children.remove(0);
-        // steal their prefix
-        let prefix = node.prefix.to_owned();
-        // drop that node just in case
-        std::mem::drop(node);
-        // replace our children with that node
-        self.children = taken;
-        // append their prefix to ours
-        self.prefix.add_assign(&prefix);
+/// the last boundary between units of key at or before at,
+/// key starting at a boundary
+fn unit_floor<K: TrieKey>(key: &[K::Atom], at: usize) -> usize {
+    let mut floor = 0;
+    while floor < at {
+        let next = floor + K::unit_len(&key[floor]);
+        if next > at {
+            break;
+        }
+        floor = next;
     }
+    floor
 }
 
 #[cfg(test)]
@@ -263,9 +1177,9 @@ mod tests {
 
     #[test]
     fn insertion_retrieval() {
-        let mut trie = Trie::new();
-        let v1 = vec!["a", "ab", "ac", "b", "c", "abc", "abcde", "abced"];
-        let v2 = vec![1, 2, 3, 4, 5, 6, 7, 9];
+        let mut trie: Trie<_> = Trie::new();
+        let v1 = ["a", "ab", "ac", "b", "c", "abc", "abcde", "abced"];
+        let v2 = [1, 2, 3, 4, 5, 6, 7, 9];
         for i in 0..8 {
             trie.set(v1[i], v2[i]);
         }
@@ -280,9 +1194,9 @@ mod tests {
 
     #[test]
     fn insertion_deletion() {
-        let mut trie = Trie::new();
-        let v1 = vec!["a", "ab", "ac", "b", "c", "abc", "abcde", "abced"];
-        let v2 = vec![1, 2, 3, 4, 5, 6, 7, 9];
+        let mut trie: Trie<_> = Trie::new();
+        let v1 = ["a", "ab", "ac", "b", "c", "abc", "abcde", "abced"];
+        let v2 = [1, 2, 3, 4, 5, 6, 7, 9];
         for i in 0..8 {
             trie.set(v1[i], v2[i]);
         }
@@ -290,17 +1204,502 @@ mod tests {
             assert_eq!(trie.get(v1[i]), Some(&v2[i]));
         }
         assert_eq!(trie.size(), 9);
+        assert_eq!(trie.len(), 8);
         let removed = trie.remove("abcd");
         assert!(removed.is_err());
         let removed = trie.remove("abcde");
         assert_eq!(removed.ok(), Some(7));
-        assert_eq!(trie.size(), 7);
+        // abc keeps its value and its other child
+        assert_eq!(trie.size(), 8);
         let removed: Result<i32, KeyNotFoundError> = trie.remove("c");
         assert_eq!(removed.ok(), Some(5));
-        assert_eq!(trie.size(), 6);
+        assert_eq!(trie.size(), 7);
         let removed = trie.remove("abcde");
         assert!(removed.is_err());
-        assert_eq!(trie.size(), 6);
+        assert_eq!(trie.size(), 7);
+        assert_eq!(trie.get("abced"), Some(&9));
+        assert!(trie.validate().is_ok());
+        assert_eq!(trie.len(), 6);
+        assert!(!trie.is_empty());
+    }
+
+    #[test]
+    fn ordered_iteration() {
+        let mut trie: Trie<_> = Trie::new();
+        // out of order, with keys splitting earlier ones
+        let keys = vec!["abced", "b", "abc", "ac", "abcde", "a", "c", "ab", "", "ba"];
+        for (i, key) in keys.iter().enumerate() {
+            trie.set(key, i);
+        }
+        let mut sorted = keys.clone();
+        sorted.sort();
+        let iterated: Vec<String> = trie.keys().collect();
+        assert_eq!(iterated, sorted);
+        for (key, value) in trie.iter() {
+            assert_eq!(keys[*value], key);
+        }
+        let values: Vec<&usize> = trie.values().collect();
+        let pairs: Vec<&usize> = trie.iter().map(|(_, value)| value).collect();
+        assert_eq!(values, pairs);
+    }
+
+    #[test]
+    fn mutable_and_moved_iteration() {
+        let mut trie: Trie<_> = Trie::new();
+        for key in ["d", "ab", "a", "abc", "b"] {
+            trie.set(key, key.len());
+        }
+        for (key, value) in trie.iter_mut() {
+            *value *= 10;
+            assert_eq!(*value, key.len() * 10);
+        }
+        assert_eq!(trie.get("abc"), Some(&30));
+        let moved: Vec<(String, usize)> = trie.into_iter().collect();
+        assert_eq!(
+            moved,
+            vec![
+                ("a".to_owned(), 10),
+                ("ab".to_owned(), 20),
+                ("abc".to_owned(), 30),
+                ("b".to_owned(), 10),
+                ("d".to_owned(), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefix_iteration() {
+        let mut trie: Trie<_> = Trie::new();
+        for key in ["car", "cart", "carton", "cat", "dog", "ca", "carbon"] {
+            trie.set(key, key.len());
+        }
+        let keys = |prefix| {
+            trie.iter_prefix(prefix)
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        // ends in the middle of the "rt" segment
+        assert_eq!(keys("cart"), vec!["cart", "carton"]);
+        assert_eq!(keys("carto"), vec!["carton"]);
+        assert_eq!(keys("car"), vec!["car", "carbon", "cart", "carton"]);
+        assert_eq!(
+            keys("c"),
+            vec!["ca", "car", "carbon", "cart", "carton", "cat"]
+        );
+        assert_eq!(keys(""), trie.keys().collect::<Vec<_>>());
+        assert!(keys("carts").is_empty());
+        assert!(keys("e").is_empty());
+        for (_, value) in trie.iter_prefix_mut("cart") {
+            *value = 0;
+        }
+        assert_eq!(trie.get("carton"), Some(&0));
+        assert_eq!(trie.get("carbon"), Some(&6));
+    }
+
+    #[test]
+    fn longest_prefix_matching() {
+        let mut trie: Trie<_> = Trie::new();
+        trie.set("/", "root");
+        trie.set("/api", "api");
+        trie.set("/api/v1", "v1");
+        trie.set("/api/v2", "v2");
+        trie.set("/static", "static");
+        assert_eq!(
+            trie.longest_prefix_match("/api/v1/users"),
+            Some(("/api/v1", &"v1"))
+        );
+        assert_eq!(trie.longest_prefix_match("/api/v3"), Some(("/api", &"api")));
+        assert_eq!(trie.longest_prefix_match("/api"), Some(("/api", &"api")));
+        assert_eq!(trie.longest_prefix_match("/stat"), Some(("/", &"root")));
+        assert_eq!(trie.longest_prefix_match("api"), None);
+        let prefixes: Vec<&str> = trie.prefixes_of("/api/v2/").map(|(key, _)| key).collect();
+        assert_eq!(prefixes, vec!["/", "/api", "/api/v2"]);
+        trie.set("", "empty");
+        assert_eq!(trie.longest_prefix_match("nope"), Some(("", &"empty")));
+    }
+
+    #[test]
+    fn unicode_keys() {
+        // cjk, emoji sequences, combining marks and characters sharing leading
+        // bytes, which nodes are still only split between
+        let keys = [
+            "日本",
+            "日本語",
+            "日曜日",
+            "本",
+            "👍",
+            "👍🏽",
+            "👨‍👩‍👧",
+            "👨‍👩‍👦",
+            "🇯🇵",
+            "🇯🇲",
+            "e\u{301}",
+            "e\u{300}",
+            "é",
+            "è",
+            "ê",
+            "e",
+            "a\u{308}\u{304}",
+            "Ωmega",
+            "ωmega",
+        ];
+        let mut trie: Trie<_> = Trie::new();
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(trie.set(key, i), None);
+            assert_eq!(trie.validate(), Ok(()));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(trie.get(key), Some(&i), "{key}");
+        }
+        // é, è and ê part on their first character, not after their shared byte
+        let prefixes: Vec<&str> = trie
+            .root
+            .children
+            .iter()
+            .map(|node| std::str::from_utf8(&node.prefix).unwrap())
+            .collect();
+        let expected = [
+            "a\u{308}\u{304}",
+            "e",
+            "è",
+            "é",
+            "ê",
+            "Ωmega",
+            "ωmega",
+            "日",
+            "本",
+            "🇯",
+            "👍",
+            "👨‍👩‍",
+        ];
+        assert_eq!(prefixes, expected);
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        assert!(trie.keys().eq(sorted.iter().map(|key| key.to_string())));
+        // lookups ending inside of a stored character
+        assert_eq!(trie.get("日"), None);
+        assert_eq!(trie.get("👨‍👩‍"), None);
+        assert_eq!(trie.get("🇯"), None);
+        assert!(!trie.has("ë"));
+
+        let keys_under = |trie: &Trie<usize>, prefix| {
+            trie.iter_prefix(prefix)
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys_under(&trie, "日"), vec!["日曜日", "日本", "日本語"]);
+        assert_eq!(keys_under(&trie, "👍"), vec!["👍", "👍🏽"]);
+        assert_eq!(keys_under(&trie, "👨‍👩‍"), vec!["👨‍👩‍👦", "👨‍👩‍👧"]);
+        // combining marks are characters of their own, unlike precomposed ones
+        assert_eq!(keys_under(&trie, "e"), vec!["e", "e\u{300}", "e\u{301}"]);
+        assert_eq!(
+            trie.longest_prefix_match("日本語の本"),
+            Some(("日本語", &1))
+        );
+        assert_eq!(
+            trie.longest_prefix_match("e\u{301}\u{302}"),
+            Some(("e\u{301}", &10))
+        );
+        let prefixes: Vec<&str> = trie.prefixes_of("👍🏽👍").map(|(key, _)| key).collect();
+        assert_eq!(prefixes, vec!["👍", "👍🏽"]);
+        let app = trie.subtrie("日").unwrap();
+        let relative: Vec<String> = app.iter().map(|(key, _)| key).collect();
+        assert_eq!(relative, vec!["曜日", "本", "本語"]);
+        let between: Vec<String> = trie.range("e".."f").map(|(key, _)| key).collect();
+        assert_eq!(between, vec!["e", "e\u{300}", "e\u{301}"]);
+
+        // removal merges nodes back together
+        for (i, key) in keys.iter().enumerate().step_by(2) {
+            assert_eq!(trie.remove(key), Ok(i));
+            assert_eq!(trie.validate(), Ok(()));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            let expected = (i % 2 == 1).then_some(i);
+            assert_eq!(trie.get(key).copied(), expected, "{key}");
+        }
+        let removed: Vec<String> = trie
+            .remove_prefix("👨")
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(removed, vec!["👨‍👩‍👦"]);
+        assert_eq!(trie.len(), keys.len() / 2 - 1);
+        assert_eq!(trie.validate(), Ok(()));
+    }
+
+    #[test]
+    fn entries() {
+        let mut trie: Trie<_> = Trie::new();
+        for word in "the cat and the other cat and then the end".split(' ') {
+            *trie.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(trie.get("the"), Some(&3));
+        assert_eq!(trie.get("then"), Some(&1));
+        assert_eq!(trie.get("cat"), Some(&2));
+        assert_eq!(trie.get("th"), None);
+        // vacant in the middle of a segment, splits it
+        assert_eq!(*trie.entry("oth").or_insert_with(|| 7), 7);
+        assert_eq!(trie.get("other"), Some(&1));
+        // vacant on an existing branching node
+        assert_eq!(trie.entry("th").key(), "th");
+        assert_eq!(*trie.entry("th").or_default(), 0);
+        *trie
+            .entry("the")
+            .and_modify(|count| *count *= 10)
+            .or_insert(0) += 1;
+        assert_eq!(trie.get("the"), Some(&31));
+        trie.entry("end").and_modify(|count| *count = 5);
+        assert_eq!(trie.get("end"), Some(&5));
+        trie.entry("ending").and_modify(|count| *count = 5);
+        assert!(!trie.has("ending"));
+        match trie.entry("cat") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert(4), 2);
+                assert_eq!(entry.remove(), 4);
+            }
+            Entry::Vacant(_) => panic!("cat should be occupied"),
+        }
+        assert!(!trie.has("cat"));
+        assert!(matches!(trie.entry("cat"), Entry::Vacant(_)));
+        assert_eq!(
+            *trie.entry("c").or_insert_with_key(|key| key.len() as i32),
+            1
+        );
+        // removing through entries compacts like remove does
+        *trie.entry("").or_default() += 2;
+        for key in ["", "then", "c", "oth"] {
+            match trie.entry(key) {
+                Entry::Occupied(entry) => entry.remove(),
+                Entry::Vacant(_) => panic!("{key} should be occupied"),
+            };
+            assert_eq!(trie.validate(), Ok(()));
+        }
+        let mut rebuilt = Trie::new();
+        for (key, value) in trie.iter() {
+            rebuilt.set(&key, *value);
+        }
+        assert_eq!(trie.len(), rebuilt.len());
+        assert_eq!(trie.size(), rebuilt.size());
+    }
+
+    #[test]
+    fn string_keys_by_default() {
+        let mut trie = Trie::new();
+        trie.set("a", 1);
+        assert_eq!(trie.get("a"), Some(&1));
+        let typed: Trie<i32> = trie;
+        assert_eq!(typed.keys().collect::<Vec<String>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn sequence_keys() {
+        // token ids
+        let mut tokens: Trie<&str, Vec<u32>> = Trie::empty();
+        tokens.set(&[7, 300, 2], "a");
+        tokens.set(&[7, 300], "b");
+        tokens.set(&[7, 1], "c");
+        tokens.set(&[], "empty");
+        assert_eq!(tokens.get(&[7, 300, 2]), Some(&"a"));
+        assert_eq!(tokens.get(&[7]), None);
+        assert_eq!(
+            tokens.keys().collect::<Vec<_>>(),
+            vec![vec![], vec![7, 1], vec![7, 300], vec![7, 300, 2]]
+        );
+        assert_eq!(
+            tokens.longest_prefix_match(&[7, 300, 5]),
+            Some((&[7, 300][..], &"b"))
+        );
+        // path segments
+        let mut paths: Trie<i32, Vec<String>> = Trie::empty();
+        let path = |p: &str| p.split('/').map(str::to_owned).collect::<Vec<_>>();
+        paths.set(&path("usr/lib"), 1);
+        paths.set(&path("usr/local/lib"), 2);
+        paths.set(&path("usr/local/bin"), 3);
+        assert_eq!(
+            paths
+                .iter_prefix(&path("usr/local"))
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        // bit strings
+        let mut bits: Trie<u8, Vec<bool>> = Trie::empty();
+        bits.set(&[true, false, true], 5);
+        bits.set(&[true, false], 2);
+        bits.set(&[false], 0);
+        *bits.entry(&[true, true]).or_insert(0) += 3;
+        assert_eq!(bits.values().copied().collect::<Vec<_>>(), vec![0, 2, 5, 3]);
+        assert!(bits.remove(&[false, true]).is_err());
+        assert_eq!(bits.get(&[true, false, true]), Some(&5));
+        // folds the node that held the removed value into its child
+        assert_eq!(bits.remove(&[true, false]).ok(), Some(2));
+        assert_eq!(bits.get(&[true, false, true]), Some(&5));
+        assert!(bits.validate().is_ok());
+    }
+
+    #[test]
+    fn prefix_removal() {
+        let mut trie: Trie<u32> = [
+            "service.api.timeout",
+            "service.api.retries",
+            "service.db",
+            "service.db.pool",
+            "services",
+            "logging.level",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (key.to_string(), i as u32))
+        .collect();
+        let api = trie.remove_prefix("service.api");
+        assert_eq!(
+            api.iter().collect::<Vec<_>>(),
+            vec![
+                ("service.api.retries".to_string(), &1),
+                ("service.api.timeout".to_string(), &0)
+            ]
+        );
+        assert_eq!((api.len(), trie.len()), (2, 4));
+        assert!(api.validate().is_ok() && trie.validate().is_ok());
+        // ends inside of a node, takes the key it ends at too
+        let drained: Vec<(String, u32)> = trie.drain_prefix("service.d").collect();
+        assert_eq!(
+            drained,
+            vec![
+                ("service.db".to_string(), 2),
+                ("service.db.pool".to_string(), 3)
+            ]
+        );
+        // service. is gone, so services folds back into a single node
+        assert!(trie.validate().is_ok());
+        assert_eq!(trie.size(), 3);
+        assert!(trie.remove_prefix("service.").is_empty());
+        assert!(trie.remove_prefix("x").is_empty());
+        assert_eq!(trie.len(), 2);
+        let rest = trie.remove_prefix("");
+        assert!(trie.is_empty());
+        assert_eq!(
+            rest.keys().collect::<Vec<_>>(),
+            vec!["logging.level", "services"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut trie: Trie<Vec<u8>> = Trie::new();
+        assert_eq!(trie.try_insert("key", vec![1]), Ok(&mut vec![1]));
+        trie.try_insert("key/child", vec![3]).unwrap().push(4);
+        let error = trie.try_insert("key", vec![2]).unwrap_err();
+        assert_eq!(
+            error,
+            KeyExistsError {
+                key: "key".to_string(),
+                value: vec![2]
+            }
+        );
+        assert_eq!(error.to_string(), "key \"key\" already exists");
+        assert_eq!(trie.get("key"), Some(&vec![1]));
+        assert_eq!(trie.get("key/child"), Some(&vec![3, 4]));
+        assert_eq!(trie.len(), 2);
+        let error = trie.remove("nope").unwrap_err();
+        assert_eq!(error.key, "nope");
+        assert_eq!(error.to_string(), "key \"nope\" not found");
+        // composes with ?
+        let take = |trie: &mut Trie<Vec<u8>>| -> Result<usize, Box<dyn Error>> {
+            let value = trie.remove("key")?;
+            trie.try_insert("key", value)?;
+            Ok(trie.remove("key")?.len())
+        };
+        assert_eq!(take(&mut trie).ok(), Some(1));
+        assert_eq!(
+            take(&mut trie).unwrap_err().to_string(),
+            "key \"key\" not found"
+        );
+        let mut sequences: Trie<(), Vec<u32>> = Trie::empty();
+        assert_eq!(
+            sequences.remove(&[1, 2]),
+            Err(KeyNotFoundError { key: vec![1, 2] })
+        );
+    }
+
+    #[test]
+    fn collection_traits() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |trie: &Trie<usize>| {
+            let mut hasher = DefaultHasher::new();
+            trie.hash(&mut hasher);
+            hasher.finish()
+        };
+        let words = ["x", "xa", "xb", "xc", "xd"];
+        let trie: Trie<usize> = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| (word.to_string(), i))
+            .collect();
+        assert_eq!(trie.len(), 5);
+        assert_eq!(trie["xc"], 3);
+        // same keys, reached through a wider child layout
+        let mut grown: Trie<usize> = Trie::default();
+        grown.extend((b'a'..=b'z').map(|c| (format!("x{}", c as char), 0)));
+        grown.extend(
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, word)| (word.to_string(), i)),
+        );
+        for c in b'e'..=b'z' {
+            grown.remove(&format!("x{}", c as char)).unwrap();
+        }
+        assert_eq!(grown, trie);
+        assert_eq!(hash(&grown), hash(&trie));
+        let mut copy = trie.clone();
+        assert_eq!(copy, trie);
+        for (_, value) in &mut copy {
+            *value += 1;
+        }
+        assert_ne!(copy, trie);
+        assert_ne!(hash(&copy), hash(&trie));
+        let total: usize = (&copy).into_iter().map(|(_, value)| value).sum();
+        assert_eq!(total, 15);
+        copy.remove("xd").unwrap();
+        assert_ne!(copy.len(), trie.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let trie: Trie<u8> = Trie::default();
+        let _ = trie["nope"];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut trie: Trie<Vec<u32>> = Trie::new();
+        for (i, key) in ["b", "ab", "a", "abc", "", "ba"].iter().enumerate() {
+            trie.set(key, vec![i as u32; i]);
+        }
+        let json = serde_json::to_string(&trie).unwrap();
+        assert_eq!(
+            json,
+            r#"{"":[4,4,4,4],"a":[2,2],"ab":[1],"abc":[3,3,3],"b":[],"ba":[5,5,5,5,5]}"#
+        );
+        let back: Trie<Vec<u32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            back.iter().collect::<Vec<_>>(),
+            trie.iter().collect::<Vec<_>>()
+        );
+        let mut bytes: Trie<i64, Vec<u8>> = Trie::empty();
+        for key in [&[1u8, 2, 3][..], &[1, 2], &[200], &[]] {
+            bytes.set(key, -(key.len() as i64));
+        }
+        let encoded = bincode::serialize(&bytes).unwrap();
+        let back: Trie<i64, Vec<u8>> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(
+            back.iter().collect::<Vec<_>>(),
+            bytes.iter().collect::<Vec<_>>()
+        );
     }
 
     #[bench]
@@ -311,7 +1710,7 @@ mod tests {
             v.push(str);
         }
         bencher.iter(|| {
-            let mut tree = Trie::new();
+            let mut tree: Trie<_> = Trie::new();
             v.iter().for_each(|s| { tree.set(s, 1); });
         });
     }
@@ -326,7 +1725,7 @@ mod tests {
             }
         }
         bencher.iter(|| {
-            let mut tree = Trie::new();
+            let mut tree: Trie<_> = Trie::new();
             v.iter().for_each(|s| { tree.set(s, 1); });
         });
     }
@@ -339,7 +1738,7 @@ mod tests {
             v.push(str);
         }
         bencher.iter(|| {
-            let mut tree = Trie::new();
+            let mut tree: Trie<_> = Trie::new();
             v.iter().for_each(|s| { tree.set(s, 1); });
         });
     }
@@ -351,7 +1750,7 @@ mod tests {
             let str = i.to_string();
             v.push(str);
         }
-        let mut tree = Trie::new();
+        let mut tree: Trie<_> = Trie::new();
         v.iter().for_each(|s| { tree.set(s, 1); });
         let mut v = vec![];
         for _ in 0..10000 {
@@ -362,7 +1761,7 @@ mod tests {
             v.iter().for_each(|s| { tree.set(s, 1); });
         });
     }
-    
+
     #[bench]
     fn dupe_longer_strings_under_load(bencher: &mut Bencher) {
         let mut v = vec![];
@@ -370,7 +1769,7 @@ mod tests {
             let str = i.to_string();
             v.push(str);
         }
-        let mut tree = Trie::new();
+        let mut tree: Trie<_> = Trie::new();
         v.iter().for_each(|s| { tree.set(s, 1); });
         let mut v = vec![];
         for _ in 0..10000 {
@@ -381,4 +1780,45 @@ mod tests {
             v.iter().for_each(|s| { tree.set(s, 1); });
         });
     }
+
+    /// every two byte key, fanning out 256 ways at the top two levels
+    fn wide_fanout_keys() -> Vec<Vec<u8>> {
+        let mut v = vec![];
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                // spread inserts out instead of going in order
+                v.push(vec![a.wrapping_mul(167), b.wrapping_mul(89), a ^ b]);
+            }
+        }
+        v
+    }
+
+    #[bench]
+    fn wide_fanout_inserts(bencher: &mut Bencher) {
+        let v = wide_fanout_keys();
+        bencher.iter(|| {
+            let mut tree: Trie<_, Vec<u8>> = Trie::empty();
+            v.iter().for_each(|s| { tree.set(s, 1); });
+        });
+    }
+
+    #[bench]
+    fn wide_fanout_lookups(bencher: &mut Bencher) {
+        let v = wide_fanout_keys();
+        let mut tree: Trie<_, Vec<u8>> = Trie::empty();
+        v.iter().for_each(|s| { tree.set(s, 1); });
+        bencher.iter(|| v.iter().filter(|s| tree.has(s)).count());
+    }
+
+    #[bench]
+    fn sequential_number_strings_lookups(bencher: &mut Bencher) {
+        let mut v = vec![];
+        for i in 0..10000 {
+            let str = i.to_string();
+            v.push(str);
+        }
+        let mut tree: Trie<_> = Trie::new();
+        v.iter().for_each(|s| { tree.set(s, 1); });
+        bencher.iter(|| v.iter().filter(|s| tree.has(s)).count());
+    }
 }
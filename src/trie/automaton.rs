@@ -0,0 +1,569 @@
+//! aho-corasick automaton over the keys of a byte keyed trie
+//!
+//! the trie is unrolled into one state per key byte. each state gets a
+//! failure link to the longest proper suffix of its key that leads to a
+//! state too, and an output link to the longest such suffix that is a key.
+//! text is fed a byte at a time, following failure links when a byte has
+//! no transition, so every occurrence of every key is seen in one pass.
+//!
+//! for leftmost-longest matches, failing from a key's state, or from a
+//! state past one, ends the scan instead, as the aho-corasick crate does.
+//! the last key seen is the match then. while a scan may still find a
+//! longer key, a second one is already running from the end of what it
+//! has found, so the scan goes on there without feeding any byte twice.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io::{self, BufReader, Read};
+use std::iter::FusedIterator;
+use std::str::Bytes;
+
+use super::{Trie, TrieKey, TrieNode};
+
+/// which occurrences a scan reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// every occurrence of every key, ordered by end, longer ones first
+    Overlapping,
+    /// going left to right, the longest key starting first,
+    /// then on from its end
+    LeftmostLongest,
+}
+
+impl<K: TrieKey<Atom = u8>, V> Trie<V, K> {
+    /// compiles the keys into an automaton finding them in text
+    ///
+    /// the empty key is left out, it occurs everywhere.
+    pub fn automaton(&self) -> Automaton<'_, V> {
+        Automaton::new(&self.root)
+    }
+}
+
+/// finds all keys of a trie in text in a single pass
+#[derive(Debug)]
+pub struct Automaton<'a, V> {
+    /// the root is the first state, states come in key order
+    states: Vec<State<'a, V>>,
+    /// length of the longest key
+    longest: usize,
+}
+
+#[derive(Debug)]
+struct State<'a, V> {
+    /// transitions, sorted by byte
+    next: Vec<(u8, usize)>,
+    /// state of the longest proper suffix of our key that has one
+    fail: usize,
+    /// state of the longest proper suffix of our key that is a key
+    output: Option<usize>,
+    /// failing from here ends a leftmost-longest scan
+    ends: bool,
+    /// state of the key a leftmost-longest scan finds here
+    leftmost: Option<usize>,
+    /// length of our key
+    depth: usize,
+    value: Option<&'a V>,
+}
+
+impl<'a, V> Automaton<'a, V> {
+    fn new(root: &'a TrieNode<u8, V>) -> Self {
+        let mut states = vec![State {
+            next: Vec::new(),
+            fail: 0,
+            output: None,
+            ends: false,
+            leftmost: None,
+            depth: 0,
+            value: None,
+        }];
+        unroll(&mut states, 0, root);
+        let longest = states.iter().map(|state| state.depth).max().unwrap_or(0);
+        // suffixes are shorter, so states are linked in order of depth
+        let mut queue = VecDeque::from([0]);
+        while let Some(parent) = queue.pop_front() {
+            for at in 0..states[parent].next.len() {
+                let (byte, child) = states[parent].next[at];
+                let fail = match parent {
+                    0 => 0,
+                    _ => step(&states, states[parent].fail, byte),
+                };
+                states[child].fail = fail;
+                states[child].output = match states[fail].value {
+                    Some(_) => Some(fail),
+                    None => states[fail].output,
+                };
+                // a key, anything past one, and anything whose failure
+                // falls back through one, would start a match later
+                let ends = states[child].value.is_some()
+                    || states[parent].ends
+                    || parent != 0 && ends_on(&states, states[parent].fail, byte);
+                states[child].ends = ends;
+                states[child].leftmost = match (states[child].value, ends) {
+                    (Some(_), _) => Some(child),
+                    (None, true) => None,
+                    (None, false) => match states[fail].value {
+                        Some(_) => Some(fail),
+                        None => states[fail].leftmost,
+                    },
+                };
+                queue.push_back(child);
+            }
+        }
+        Automaton { states, longest }
+    }
+
+    /// gets the number of keys looked for
+    pub fn len(&self) -> usize {
+        self.states
+            .iter()
+            .filter(|state| state.value.is_some())
+            .count()
+    }
+
+    /// checks if there are no keys to look for
+    pub fn is_empty(&self) -> bool {
+        self.longest == 0
+    }
+
+    /// finds keys in text
+    ///
+    /// yields the byte range of each occurrence with the value of its key.
+    pub fn scan<'s>(&'s self, text: &'s str, kind: MatchKind) -> Occurrences<'s, V> {
+        Occurrences {
+            scanner: Scanner::new(self, kind),
+            bytes: text.bytes(),
+        }
+    }
+
+    /// finds keys in what a reader gives, reading it as it goes
+    ///
+    /// yields the byte range of each occurrence with the value of its key,
+    /// or the error reading failed with.
+    pub fn scan_read<R: Read>(&self, reader: R, kind: MatchKind) -> StreamOccurrences<'_, V, R> {
+        StreamOccurrences {
+            scanner: Scanner::new(self, kind),
+            bytes: BufReader::new(reader).bytes(),
+        }
+    }
+}
+
+/// adds a chain of states for each child's prefix below state
+///
+/// siblings starting with the same bytes of a character share the states
+/// for those bytes.
+fn unroll<'a, V>(states: &mut Vec<State<'a, V>>, state: usize, node: &'a TrieNode<u8, V>) {
+    for child in node.children.iter() {
+        let mut at = state;
+        for byte in child.prefix.iter() {
+            // children come sorted, so a shared transition is the last one
+            if let Some(&(last, next)) = states[at].next.last() {
+                if last == *byte {
+                    at = next;
+                    continue;
+                }
+            }
+            let next = states.len();
+            states.push(State {
+                next: Vec::new(),
+                fail: 0,
+                output: None,
+                ends: false,
+                leftmost: None,
+                depth: states[at].depth + 1,
+                value: None,
+            });
+            // children come sorted, so transitions do too
+            states[at].next.push((*byte, next));
+            at = next;
+        }
+        states[at].value = child.value.as_ref();
+        unroll(states, at, child);
+    }
+}
+
+/// follows byte from state, falling back along failure links
+fn step<V>(states: &[State<'_, V>], mut state: usize, byte: u8) -> usize {
+    loop {
+        let next = &states[state].next;
+        if let Ok(at) = next.binary_search_by_key(&byte, |(byte, _)| *byte) {
+            return next[at].1;
+        }
+        if state == 0 {
+            return 0;
+        }
+        state = states[state].fail;
+    }
+}
+
+/// checks if following byte from state falls back through an ending state
+fn ends_on<V>(states: &[State<'_, V>], mut state: usize, byte: u8) -> bool {
+    loop {
+        let next = &states[state].next;
+        if state == 0 || next.binary_search_by_key(&byte, |(byte, _)| *byte).is_ok() {
+            return false;
+        }
+        if states[state].ends {
+            return true;
+        }
+        state = states[state].fail;
+    }
+}
+
+/// follows byte from state for a leftmost-longest scan, none if it ends
+fn step_leftmost<V>(states: &[State<'_, V>], mut state: usize, byte: u8) -> Option<usize> {
+    loop {
+        let next = &states[state].next;
+        if let Ok(at) = next.binary_search_by_key(&byte, |(byte, _)| *byte) {
+            return Some(next[at].1);
+        }
+        if state == 0 {
+            return Some(0);
+        }
+        if states[state].ends {
+            return None;
+        }
+        state = states[state].fail;
+    }
+}
+
+/// an occurrence, as byte range and value of the key
+type Occurrence<'a, V> = (usize, usize, &'a V);
+
+/// the state of a scan, independent of where bytes come from
+struct Scanner<'a, V> {
+    automaton: &'a Automaton<'a, V>,
+    kind: MatchKind,
+    state: usize,
+    /// offset of the next byte fed
+    pos: usize,
+    /// occurrences found, not yet handed out
+    found: VecDeque<Occurrence<'a, V>>,
+    /// leftmost-longest scans, each starting where the one before
+    /// found its occurrence so far
+    runs: VecDeque<Run<'a, V>>,
+}
+
+/// a leftmost-longest scan
+struct Run<'a, V> {
+    /// none once the scan ended
+    state: Option<usize>,
+    /// longest occurrence starting first, found so far
+    found: Option<Occurrence<'a, V>>,
+}
+
+impl<V> Run<'_, V> {
+    fn new() -> Self {
+        Run {
+            state: Some(0),
+            found: None,
+        }
+    }
+}
+
+impl<'a, V> Scanner<'a, V> {
+    fn new(automaton: &'a Automaton<'a, V>, kind: MatchKind) -> Self {
+        Scanner {
+            automaton,
+            kind,
+            state: 0,
+            pos: 0,
+            found: VecDeque::new(),
+            runs: VecDeque::from([Run::new()]),
+        }
+    }
+
+    /// finds the next occurrence, taking bytes from input as needed
+    fn next<E, I>(&mut self, input: &mut I) -> Option<Result<Occurrence<'a, V>, E>>
+    where
+        I: Iterator<Item = Result<u8, E>>,
+    {
+        loop {
+            if let Some(found) = self.found.pop_front() {
+                return Some(Ok(found));
+            }
+            match input.next() {
+                Some(Ok(byte)) => self.feed(byte),
+                Some(Err(err)) => return Some(Err(err)),
+                // nothing left to outdo what the runs found
+                None if !self.runs.is_empty() => {
+                    let runs = std::mem::take(&mut self.runs);
+                    self.found
+                        .extend(runs.into_iter().map_while(|run| run.found));
+                }
+                None => return None,
+            }
+        }
+    }
+
+    fn feed(&mut self, byte: u8) {
+        let states = &self.automaton.states;
+        self.pos += 1;
+        match self.kind {
+            MatchKind::Overlapping => {
+                self.state = step(states, self.state, byte);
+                let state = &states[self.state];
+                let mut key = match state.value {
+                    Some(_) => Some(self.state),
+                    None => state.output,
+                };
+                while let Some(at) = key {
+                    let found = &states[at];
+                    self.found
+                        .push_back((self.pos - found.depth, self.pos, found.value.unwrap()));
+                    key = found.output;
+                }
+            }
+            MatchKind::LeftmostLongest => {
+                for at in 0..self.runs.len() {
+                    let run = &mut self.runs[at];
+                    let Some(state) = run.state else {
+                        continue;
+                    };
+                    run.state = step_leftmost(states, state, byte);
+                    let Some(key) = run.state.and_then(|state| states[state].leftmost) else {
+                        continue;
+                    };
+                    // a longer occurrence, the runs after it start over here
+                    let found = &states[key];
+                    run.found = Some((self.pos - found.depth, self.pos, found.value.unwrap()));
+                    self.runs.truncate(at + 1);
+                    self.runs.push_back(Run::new());
+                    break;
+                }
+                // ended runs found their occurrence for good
+                while self.runs.front().is_some_and(|run| run.state.is_none()) {
+                    let run = self.runs.pop_front().unwrap();
+                    self.found.extend(run.found);
+                }
+                if self.runs.is_empty() {
+                    self.runs.push_back(Run::new());
+                }
+            }
+        }
+    }
+}
+
+/// occurrences of the keys of an automaton in a string
+pub struct Occurrences<'a, V> {
+    scanner: Scanner<'a, V>,
+    bytes: Bytes<'a>,
+}
+
+/// occurrences of the keys of an automaton in what a reader gives
+pub struct StreamOccurrences<'a, V, R> {
+    scanner: Scanner<'a, V>,
+    bytes: io::Bytes<BufReader<R>>,
+}
+
+impl<'a, V> Iterator for Occurrences<'a, V> {
+    type Item = (usize, usize, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = self.bytes.by_ref().map(Ok::<u8, Infallible>);
+        match self.scanner.next(&mut bytes)? {
+            Ok(found) => Some(found),
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl<'a, V, R: Read> Iterator for StreamOccurrences<'a, V, R> {
+    type Item = io::Result<(usize, usize, &'a V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.scanner.next(&mut self.bytes)
+    }
+}
+
+impl<V> FusedIterator for Occurrences<'_, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::{words, Random};
+
+    /// reads a byte at a time, failing where told to
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        fail_at: Option<usize>,
+        read: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_at == Some(self.read) {
+                self.fail_at = None;
+                return Err(io::Error::other("broken"));
+            }
+            if self.read == self.bytes.len() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.bytes[self.read];
+            self.read += 1;
+            Ok(1)
+        }
+    }
+
+    fn overlapping(keys: &[&str], text: &str) -> Vec<(usize, usize, usize)> {
+        let mut found = Vec::new();
+        for end in 1..=text.len() {
+            let mut here: Vec<(usize, usize, usize)> = keys
+                .iter()
+                .enumerate()
+                .filter(|(_, key)| {
+                    !key.is_empty() && text.as_bytes()[..end].ends_with(key.as_bytes())
+                })
+                .map(|(i, key)| (end - key.len(), end, i))
+                .collect();
+            here.sort();
+            found.extend(here);
+        }
+        found
+    }
+
+    fn leftmost_longest(keys: &[&str], text: &str) -> Vec<(usize, usize, usize)> {
+        let mut found = Vec::new();
+        let mut pos = 0;
+        while pos < text.len() {
+            let best = (pos..text.len()).find_map(|start| {
+                keys.iter()
+                    .enumerate()
+                    .filter(|(_, key)| {
+                        !key.is_empty() && text.as_bytes()[start..].starts_with(key.as_bytes())
+                    })
+                    .max_by_key(|(_, key)| key.len())
+                    .map(|(i, key)| (start, start + key.len(), i))
+            });
+            match best {
+                Some(best) => {
+                    pos = best.1;
+                    found.push(best);
+                }
+                None => break,
+            }
+        }
+        found
+    }
+
+    fn scan(trie: &Trie<usize>, text: &str, kind: MatchKind) -> Vec<(usize, usize, usize)> {
+        let automaton = trie.automaton();
+        let found: Vec<(usize, usize, usize)> = automaton
+            .scan(text, kind)
+            .map(|(start, end, value)| (start, end, *value))
+            .collect();
+        let streamed: Vec<(usize, usize, usize)> = automaton
+            .scan_read(
+                Trickle {
+                    bytes: text.as_bytes(),
+                    fail_at: None,
+                    read: 0,
+                },
+                kind,
+            )
+            .map(|found| {
+                found
+                    .map(|(start, end, value)| (start, end, *value))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(found, streamed);
+        found
+    }
+
+    #[test]
+    fn banned_terms() {
+        let keys = ["he", "she", "his", "hers", "usher"];
+        let trie = words(&keys);
+        let automaton = trie.automaton();
+        assert_eq!(automaton.len(), 5);
+        assert_eq!(
+            scan(&trie, "ushers", MatchKind::Overlapping),
+            vec![(1, 4, 1), (2, 4, 0), (0, 5, 4), (2, 6, 3)]
+        );
+        assert_eq!(
+            scan(&trie, "ushers", MatchKind::LeftmostLongest),
+            vec![(0, 5, 4)]
+        );
+        assert_eq!(
+            scan(&trie, "she said his hershey", MatchKind::LeftmostLongest),
+            vec![(0, 3, 1), (9, 12, 2), (13, 17, 3), (17, 19, 0)]
+        );
+        // byte offsets, keys may be any utf-8
+        let trie = words(&["日本", "本語", "語"]);
+        assert_eq!(
+            scan(&trie, "日本語", MatchKind::Overlapping),
+            vec![(0, 6, 0), (3, 9, 1), (6, 9, 2)]
+        );
+        assert_eq!(
+            scan(&trie, "日本語", MatchKind::LeftmostLongest),
+            vec![(0, 6, 0), (6, 9, 2)]
+        );
+        // siblings sharing the leading bytes of a character share their states
+        let trie = words(&["café", "cafè", "fè"]);
+        assert_eq!(
+            scan(&trie, "cafè et café", MatchKind::Overlapping),
+            vec![(0, 5, 1), (2, 5, 2), (9, 14, 0)]
+        );
+        // the empty key is never reported
+        let trie = words(&[""]);
+        assert!(trie.automaton().is_empty());
+        assert_eq!(scan(&trie, "abc", MatchKind::Overlapping), vec![]);
+    }
+
+    #[test]
+    fn read_errors() {
+        let trie = words(&["ab", "b"]);
+        let automaton = trie.automaton();
+        let mut found = automaton.scan_read(
+            Trickle {
+                bytes: b"abab",
+                fail_at: Some(3),
+                read: 0,
+            },
+            MatchKind::Overlapping,
+        );
+        assert_eq!(found.next().unwrap().unwrap(), (0, 2, &0));
+        assert_eq!(found.next().unwrap().unwrap(), (1, 2, &1));
+        assert!(found.next().unwrap().is_err());
+        // reading goes on past the error
+        assert_eq!(found.next().unwrap().unwrap(), (2, 4, &0));
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut random = Random(0x2545f4914f6cdd1d);
+        for _ in 0..200 {
+            let picked: Vec<String> = (0..1 + random.next(6))
+                .map(|_| {
+                    (0..random.next(5))
+                        .map(|_| (b'a' + random.next(3) as u8) as char)
+                        .collect()
+                })
+                .collect();
+            let keys: Vec<&str> = picked.iter().map(|word| word.as_str()).collect();
+            // duplicates keep their last value
+            let trie = words(&keys);
+            let keys: Vec<&str> = keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| match keys[i + 1..].contains(key) {
+                    true => "",
+                    false => *key,
+                })
+                .collect();
+            let text: String = (0..random.next(40))
+                .map(|_| (b'a' + random.next(3) as u8) as char)
+                .collect();
+            assert_eq!(
+                scan(&trie, &text, MatchKind::Overlapping),
+                overlapping(&keys, &text),
+                "{keys:?} in {text:?}"
+            );
+            assert_eq!(
+                scan(&trie, &text, MatchKind::LeftmostLongest),
+                leftmost_longest(&keys, &text),
+                "{keys:?} in {text:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! adaptive child layout for trie nodes
+//!
+//! children are keyed by the first unit of their prefix, which is unique
+//! among siblings. units are mostly single atoms, but siblings whose units
+//! span several atoms, like the bytes of a character, can share a first atom.
+//! the layout grows and shrinks with the number of children, in the style of
+//! an adaptive radix tree:
+//!
+//! * node4 and node16 keep children sorted in a vector, scanned linearly
+//!   and binary searched respectively.
+//! * node48 keeps them sorted as well, with a radix byte to position table.
+//! * node256 has one slot per radix byte.
+//!
+//! the table layouts need atoms that map onto a radix byte, and siblings
+//! that part on their first atom. other children stay in node16 past 16.
+
+use std::iter::FusedIterator;
+use std::{slice, vec};
+
+use super::{TrieAtom, TrieNode};
+
+/// marks an unused entry of a node48 position table
+const EMPTY: u8 = u8::MAX;
+
+/// a node256 slot
+type Slot<A, V> = Option<Box<TrieNode<A, V>>>;
+
+#[derive(Debug, Clone)]
+pub(super) enum Children<A, V> {
+    Node4(Vec<TrieNode<A, V>>),
+    Node16(Vec<TrieNode<A, V>>),
+    Node48(Box<[u8; 256]>, Vec<TrieNode<A, V>>),
+    /// slots, and the number of them in use
+    Node256(Box<[Slot<A, V>]>, usize),
+}
+
+/// ordered iteration over children
+///
+/// only one of the two iterators is ever non empty,
+/// depending on whether the layout is sorted or slotted.
+pub(super) struct ChildIter<'a, A, V> {
+    sorted: slice::Iter<'a, TrieNode<A, V>>,
+    slots: slice::Iter<'a, Slot<A, V>>,
+}
+
+pub(super) struct ChildIterMut<'a, A, V> {
+    sorted: slice::IterMut<'a, TrieNode<A, V>>,
+    slots: slice::IterMut<'a, Slot<A, V>>,
+}
+
+pub(super) struct ChildIntoIter<A, V> {
+    sorted: vec::IntoIter<TrieNode<A, V>>,
+    slots: vec::IntoIter<Slot<A, V>>,
+}
+
+impl<A: TrieAtom, V> Children<A, V> {
+    pub(super) fn new() -> Self {
+        Children::Node4(Vec::new())
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Children::Node4(nodes) | Children::Node16(nodes) | Children::Node48(_, nodes) => {
+                nodes.len()
+            }
+            Children::Node256(_, len) => *len,
+        }
+    }
+
+    /// gets the child key leads into, whose prefix and key agree
+    /// up to the shorter of the two
+    ///
+    /// key is at least the first unit of a key, which one child at most starts with.
+    pub(super) fn get(&self, key: &[A]) -> Option<&TrieNode<A, V>> {
+        let atom = key.first()?;
+        match self {
+            Children::Node4(nodes) => nodes.iter().find(|node| leads(&node.prefix, key)),
+            Children::Node16(nodes) => find(nodes, key).map(|idx| &nodes[idx]),
+            Children::Node48(index, nodes) => match index[radix(atom)] {
+                EMPTY => None,
+                idx => Some(&nodes[idx as usize]),
+            },
+            Children::Node256(slots, _) => slots[radix(atom)].as_deref(),
+        }
+        .filter(|node| leads(&node.prefix, key))
+    }
+
+    /// gets the child key leads into, as mutable
+    pub(super) fn get_mut(&mut self, key: &[A]) -> Option<&mut TrieNode<A, V>> {
+        let atom = key.first()?;
+        match self {
+            Children::Node4(nodes) => nodes.iter_mut().find(|node| leads(&node.prefix, key)),
+            Children::Node16(nodes) => find(nodes, key).map(|idx| &mut nodes[idx]),
+            Children::Node48(index, nodes) => match index[radix(atom)] {
+                EMPTY => None,
+                idx => Some(&mut nodes[idx as usize]),
+            },
+            Children::Node256(slots, _) => slots[radix(atom)].as_deref_mut(),
+        }
+        .filter(|node| leads(&node.prefix, key))
+    }
+
+    /// adds a child, its first unit must not be taken by another child.
+    ///
+    /// returns the added child.
+    pub(super) fn insert(&mut self, node: TrieNode<A, V>) -> &mut TrieNode<A, V> {
+        self.grow(&node.prefix[0]);
+        match self {
+            Children::Node4(nodes) | Children::Node16(nodes) => {
+                let idx = nodes.partition_point(|other| other.prefix < node.prefix);
+                nodes.insert(idx, node);
+                &mut nodes[idx]
+            }
+            Children::Node48(index, nodes) => {
+                let idx = nodes.partition_point(|other| other.prefix < node.prefix);
+                nodes.insert(idx, node);
+                // everything from idx on moved up a position
+                for (pos, node) in nodes.iter().enumerate().skip(idx) {
+                    index[radix(&node.prefix[0])] = pos as u8;
+                }
+                &mut nodes[idx]
+            }
+            Children::Node256(slots, len) => {
+                *len += 1;
+                let slot = radix(&node.prefix[0]);
+                slots[slot].insert(Box::new(node))
+            }
+        }
+    }
+
+    /// removes the child key leads into
+    pub(super) fn remove(&mut self, key: &[A]) -> Option<TrieNode<A, V>> {
+        let atom = key.first()?;
+        let removed = match self {
+            Children::Node4(nodes) => {
+                let idx = nodes.iter().position(|node| leads(&node.prefix, key))?;
+                nodes.remove(idx)
+            }
+            Children::Node16(nodes) => {
+                let idx = find(nodes, key)?;
+                nodes.remove(idx)
+            }
+            Children::Node48(index, nodes) => {
+                let idx = match index[radix(atom)] {
+                    EMPTY => return None,
+                    idx => idx as usize,
+                };
+                if !leads(&nodes[idx].prefix, key) {
+                    return None;
+                }
+                index[radix(atom)] = EMPTY;
+                let removed = nodes.remove(idx);
+                for (pos, node) in nodes.iter().enumerate().skip(idx) {
+                    index[radix(&node.prefix[0])] = pos as u8;
+                }
+                removed
+            }
+            Children::Node256(slots, len) => {
+                let slot = &mut slots[radix(atom)];
+                if !leads(&slot.as_ref()?.prefix, key) {
+                    return None;
+                }
+                let removed = slot.take()?;
+                *len -= 1;
+                *removed
+            }
+        };
+        self.shrink();
+        Some(removed)
+    }
+
+    /// estimates the bytes allocated by the layout itself,
+    /// not counting what the children allocate in turn
+    pub(super) fn heap_bytes(&self) -> usize {
+        let node = std::mem::size_of::<TrieNode<A, V>>();
+        match self {
+            Children::Node4(sorted) | Children::Node16(sorted) => sorted.capacity() * node,
+            Children::Node48(_, sorted) => 256 + sorted.capacity() * node,
+            Children::Node256(slots, used) => {
+                slots.len() * std::mem::size_of::<Slot<A, V>>() + used * node
+            }
+        }
+    }
+
+    /// takes the only child out
+    pub(super) fn take_only(&mut self) -> TrieNode<A, V> {
+        // this only makes sense if there is only 1 node.
+        assert!(self.len() == 1);
+        std::mem::replace(self, Children::new())
+            .into_iter()
+            .next()
+            .unwrap()
+    }
+
+    /// iterates children in order of their first unit
+    pub(super) fn iter(&self) -> ChildIter<'_, A, V> {
+        match self {
+            Children::Node4(nodes) | Children::Node16(nodes) | Children::Node48(_, nodes) => {
+                ChildIter {
+                    sorted: nodes.iter(),
+                    slots: [].iter(),
+                }
+            }
+            Children::Node256(slots, _) => ChildIter {
+                sorted: [].iter(),
+                slots: slots.iter(),
+            },
+        }
+    }
+
+    /// iterates children as mutable in order of their first unit
+    pub(super) fn iter_mut(&mut self) -> ChildIterMut<'_, A, V> {
+        match self {
+            Children::Node4(nodes) | Children::Node16(nodes) | Children::Node48(_, nodes) => {
+                ChildIterMut {
+                    sorted: nodes.iter_mut(),
+                    slots: [].iter_mut(),
+                }
+            }
+            Children::Node256(slots, _) => ChildIterMut {
+                sorted: [].iter_mut(),
+                slots: slots.iter_mut(),
+            },
+        }
+    }
+
+    /// switches to a bigger layout if full, or back to a sorted one if
+    /// a table layout would have to hold two children in one place
+    ///
+    /// atom is the first atom of the child about to be added.
+    fn grow(&mut self, atom: &A) {
+        let replaced = match self {
+            Children::Node4(nodes) if nodes.len() == 4 => {
+                let mut nodes = std::mem::take(nodes);
+                nodes.reserve_exact(12);
+                Children::Node16(nodes)
+            }
+            Children::Node48(index, nodes) if index[radix(atom)] != EMPTY => {
+                Children::Node16(std::mem::take(nodes))
+            }
+            Children::Node256(slots, _) if slots[radix(atom)].is_some() => {
+                let nodes = slots
+                    .iter_mut()
+                    .filter_map(Option::take)
+                    .map(|node| *node)
+                    .collect();
+                Children::Node16(nodes)
+            }
+            Children::Node16(nodes)
+                if (16..48).contains(&nodes.len())
+                    && atom.radix().is_some()
+                    && parted(nodes, atom) =>
+            {
+                let mut index = Box::new([EMPTY; 256]);
+                for (pos, node) in nodes.iter().enumerate() {
+                    index[radix(&node.prefix[0])] = pos as u8;
+                }
+                let mut nodes = std::mem::take(nodes);
+                nodes.reserve_exact(32);
+                Children::Node48(index, nodes)
+            }
+            // grown past node48 while siblings shared first atoms
+            Children::Node16(nodes)
+                if nodes.len() >= 48 && atom.radix().is_some() && parted(nodes, atom) =>
+            {
+                slotted(std::mem::take(nodes))
+            }
+            Children::Node48(_, nodes) if nodes.len() == 48 => slotted(std::mem::take(nodes)),
+            _ => return,
+        };
+        *self = replaced;
+    }
+
+    /// switches to a smaller layout once sparse enough,
+    /// leaving some room so a node doesn't flip back and forth
+    fn shrink(&mut self) {
+        let replaced = match self {
+            Children::Node16(nodes) if nodes.len() <= 3 => {
+                let mut nodes = std::mem::take(nodes);
+                nodes.shrink_to(4);
+                Children::Node4(nodes)
+            }
+            Children::Node48(_, nodes) if nodes.len() <= 12 => {
+                let mut nodes = std::mem::take(nodes);
+                nodes.shrink_to(16);
+                Children::Node16(nodes)
+            }
+            Children::Node256(slots, len) if *len <= 40 => {
+                let nodes: Vec<TrieNode<A, V>> = slots
+                    .iter_mut()
+                    .filter_map(Option::take)
+                    .map(|node| *node)
+                    .collect();
+                let mut index = Box::new([EMPTY; 256]);
+                for (pos, node) in nodes.iter().enumerate() {
+                    index[radix(&node.prefix[0])] = pos as u8;
+                }
+                Children::Node48(index, nodes)
+            }
+            _ => return,
+        };
+        *self = replaced;
+    }
+}
+
+/// position of the child key leads into, in children sorted by prefix
+///
+/// siblings differ within their first unit, so comparing up to the
+/// shorter of prefix and key keeps the search ordered.
+fn find<A: TrieAtom, V>(nodes: &[TrieNode<A, V>], key: &[A]) -> Option<usize> {
+    nodes
+        .binary_search_by(|node| {
+            let len = node.prefix.len().min(key.len());
+            node.prefix[..len].cmp(&key[..len])
+        })
+        .ok()
+}
+
+/// checks if prefix and key agree up to the shorter of the two
+#[inline]
+fn leads<A: Eq>(prefix: &[A], key: &[A]) -> bool {
+    let len = prefix.len().min(key.len());
+    prefix[..len] == key[..len]
+}
+
+/// checks if children sorted by prefix, and one more child starting with
+/// atom, all have a first atom of their own
+fn parted<A: TrieAtom, V>(nodes: &[TrieNode<A, V>], atom: &A) -> bool {
+    nodes
+        .windows(2)
+        .all(|pair| pair[0].prefix[0] != pair[1].prefix[0])
+        && nodes.iter().all(|node| node.prefix[0] != *atom)
+}
+
+/// puts children with first atoms of their own into a node256
+fn slotted<A: TrieAtom, V>(nodes: Vec<TrieNode<A, V>>) -> Children<A, V> {
+    let len = nodes.len();
+    let mut slots: Box<[Slot<A, V>]> = (0..256).map(|_| None).collect();
+    for node in nodes {
+        let slot = radix(&node.prefix[0]);
+        slots[slot] = Some(Box::new(node));
+    }
+    Children::Node256(slots, len)
+}
+
+/// radix byte of an atom held by a table layout
+#[inline]
+fn radix<A: TrieAtom>(atom: &A) -> usize {
+    // table layouts are only ever built from atoms that have one
+    atom.radix().unwrap() as usize
+}
+
+impl<A, V> IntoIterator for Children<A, V> {
+    type Item = TrieNode<A, V>;
+    type IntoIter = ChildIntoIter<A, V>;
+
+    fn into_iter(self) -> ChildIntoIter<A, V> {
+        match self {
+            Children::Node4(nodes) | Children::Node16(nodes) | Children::Node48(_, nodes) => {
+                ChildIntoIter {
+                    sorted: nodes.into_iter(),
+                    slots: Vec::new().into_iter(),
+                }
+            }
+            Children::Node256(slots, _) => ChildIntoIter {
+                sorted: Vec::new().into_iter(),
+                slots: slots.into_vec().into_iter(),
+            },
+        }
+    }
+}
+
+impl<'a, A, V> Iterator for ChildIter<'a, A, V> {
+    type Item = &'a TrieNode<A, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next()
+            .or_else(|| self.slots.find_map(|slot| slot.as_deref()))
+    }
+}
+
+impl<'a, A, V> DoubleEndedIterator for ChildIter<'a, A, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next_back()
+            .or_else(|| self.slots.rfind(|slot| slot.is_some())?.as_deref())
+    }
+}
+
+impl<'a, A, V> Iterator for ChildIterMut<'a, A, V> {
+    type Item = &'a mut TrieNode<A, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next()
+            .or_else(|| self.slots.find_map(|slot| slot.as_deref_mut()))
+    }
+}
+
+impl<'a, A, V> DoubleEndedIterator for ChildIterMut<'a, A, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next_back()
+            .or_else(|| self.slots.rfind(|slot| slot.is_some())?.as_deref_mut())
+    }
+}
+
+impl<A, V> Iterator for ChildIntoIter<A, V> {
+    type Item = TrieNode<A, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next()
+            .or_else(|| self.slots.find_map(|slot| slot.map(|node| *node)))
+    }
+}
+
+impl<A, V> DoubleEndedIterator for ChildIntoIter<A, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.sorted
+            .next_back()
+            .or_else(|| self.slots.rfind(Option::is_some)?.map(|node| *node))
+    }
+}
+
+impl<A, V> FusedIterator for ChildIter<'_, A, V> {}
+
+impl<A, V> FusedIterator for ChildIterMut<'_, A, V> {}
+
+impl<A, V> FusedIterator for ChildIntoIter<A, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Trie;
+    use super::*;
+
+    fn layout<A, V>(children: &Children<A, V>) -> usize {
+        match children {
+            Children::Node4(_) => 4,
+            Children::Node16(_) => 16,
+            Children::Node48(..) => 48,
+            Children::Node256(..) => 256,
+        }
+    }
+
+    #[test]
+    fn grows_and_shrinks() {
+        let mut trie: Trie<usize, Vec<u8>> = Trie::empty();
+        // scattered order, every byte once
+        let bytes: Vec<u8> = (0..=255u8).map(|b| b.wrapping_mul(73)).collect();
+        for (count, byte) in bytes.iter().enumerate() {
+            trie.set(&[*byte, 1], *byte as usize);
+            let expected = match count + 1 {
+                0..=4 => 4,
+                5..=16 => 16,
+                17..=48 => 48,
+                _ => 256,
+            };
+            assert_eq!(layout(&trie.root.children), expected);
+        }
+        let keys: Vec<Vec<u8>> = trie.keys().collect();
+        let sorted: Vec<Vec<u8>> = (0..=255u8).map(|b| vec![b, 1]).collect();
+        assert_eq!(keys, sorted);
+        let reversed: Vec<u8> = trie
+            .root
+            .children
+            .iter()
+            .rev()
+            .map(|node| node.prefix[0])
+            .collect();
+        assert_eq!(reversed, (0..=255u8).rev().collect::<Vec<_>>());
+        for byte in bytes.iter().take(253) {
+            assert_eq!(trie.remove(&[*byte, 1]).ok(), Some(*byte as usize));
+            assert!(!trie.has(&[*byte, 1]));
+        }
+        assert_eq!(layout(&trie.root.children), 4);
+        for byte in bytes.iter().skip(253) {
+            assert_eq!(trie.get(&[*byte, 1]), Some(&(*byte as usize)));
+        }
+        let mut remaining: Vec<u8> = bytes[253..].to_vec();
+        remaining.sort();
+        assert_eq!(
+            trie.values().map(|v| *v as u8).collect::<Vec<_>>(),
+            remaining
+        );
+    }
+
+    #[test]
+    fn shared_first_atoms() {
+        let mut trie: Trie<u32> = Trie::new();
+        let latin: Vec<char> = ('a'..='z').chain('À'..='ÿ').collect();
+        for c in latin.iter() {
+            trie.set(&c.to_string(), *c as u32);
+        }
+        // the accented letters all start with byte 0xc3, no table can hold them
+        assert_eq!(layout(&trie.root.children), 16);
+        assert_eq!(trie.validate(), Ok(()));
+        assert!(trie.keys().eq(latin.iter().map(|c| c.to_string())));
+        for c in 'À'..='þ' {
+            assert_eq!(trie.remove(&c.to_string()), Ok(c as u32));
+        }
+        assert_eq!(layout(&trie.root.children), 16);
+        // first atoms are unique again, so the next child brings in a table
+        trie.set("{", 0);
+        assert_eq!(layout(&trie.root.children), 48);
+        trie.set("é", 1);
+        assert_eq!(layout(&trie.root.children), 16);
+        assert_eq!(trie.get("é"), Some(&1));
+        assert_eq!(trie.get("ÿ"), Some(&('ÿ' as u32)));
+        assert_eq!(trie.get("ý"), None);
+        assert_eq!(trie.validate(), Ok(()));
+    }
+
+    #[test]
+    fn searched_atoms_stay_sorted() {
+        let mut trie: Trie<u32, Vec<u32>> = Trie::empty();
+        for i in (0..100u32).rev() {
+            trie.set(&[i * 1000], i);
+        }
+        assert_eq!(layout(&trie.root.children), 16);
+        assert_eq!(
+            trie.values().copied().collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+        assert_eq!(trie.get(&[42000]), Some(&42));
+        assert_eq!(trie.get(&[42001]), None);
+    }
+}
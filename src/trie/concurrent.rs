@@ -0,0 +1,440 @@
+//! prefix tree shareable between threads
+//!
+//! every node sits behind its own lock. threads walk down holding read locks
+//! on every node above them, so a node can only be restructured by whoever
+//! holds the write lock of its parent, and nobody can be below it then.
+//! locks are only ever taken top down, which keeps the walk deadlock free.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::{common_prefix_len, first_unit, unit_floor, Trie, TrieKey};
+
+/// compressed prefix tree with per node locking
+///
+/// readers only block writers restructuring a node on their path,
+/// writers in different subtrees do not block each other.
+#[derive(Debug)]
+pub struct ConcurrentTrie<K: TrieKey, V> {
+    /// tree root
+    /// this will always be a node with the empty prefix.
+    root: RwLock<ConcurrentNode<K::Atom, V>>,
+    /// number of keys
+    len: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct ConcurrentNode<A, V> {
+    prefix: Vec<A>,
+    /// sorted by first unit, which is unique among siblings.
+    /// the first unit is kept outside of the lock for lookups.
+    children: Vec<Child<A, V>>,
+    value: Option<V>,
+}
+
+type Child<A, V> = (Vec<A>, Box<RwLock<ConcurrentNode<A, V>>>);
+
+/// locks for reading, a panicking writer leaves the tree consistent
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// locks for writing, a panicking writer leaves the tree consistent
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// gets a child we have exclusive access to through its parent
+fn exclusive<T>(lock: &mut RwLock<T>) -> &mut T {
+    lock.get_mut().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<K: TrieKey, V> ConcurrentTrie<K, V> {
+    /// constructs an empty prefix tree
+    pub fn new() -> Self {
+        ConcurrentTrie {
+            root: RwLock::new(ConcurrentNode::with_prefix(Vec::new(), None)),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// gets a copy of the value of a key
+    pub fn get(&self, key: &K::Slice) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.get_with(key, V::clone)
+    }
+
+    /// calls f with the value of a key, while it is read locked
+    pub fn get_with<R, F: FnOnce(&V) -> R>(&self, key: &K::Slice, f: F) -> Option<R> {
+        ConcurrentNode::get_with(&self.root, K::atoms(key), f)
+    }
+
+    /// checks if a key exists
+    #[inline]
+    pub fn has(&self, key: &K::Slice) -> bool {
+        self.get_with(key, |_| ()).is_some()
+    }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// sets a key to a value
+    ///
+    /// returns the previous value, if any.
+    pub fn set(&self, key: &K::Slice, val: V) -> Option<V> {
+        // the root's prefix is empty, it always takes the key
+        let Ok(old) = ConcurrentNode::set::<K>(&self.root, K::atoms(key), val) else {
+            unreachable!("the root covers every key");
+        };
+        if old.is_none() {
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
+        old
+    }
+
+    /// removes a key
+    ///
+    /// returns its value, if it existed.
+    pub fn remove(&self, key: &K::Slice) -> Option<V> {
+        let (old, _) = ConcurrentNode::remove(&self.root, K::atoms(key))?;
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(old)
+    }
+
+    /// copies the keys and values into a plain trie
+    ///
+    /// subtrees are copied one after another, so this is not a point in time
+    /// copy while other threads keep writing.
+    pub fn snapshot(&self) -> Trie<V, K>
+    where
+        V: Clone,
+    {
+        let mut trie = Trie::empty();
+        let mut key = Vec::new();
+        ConcurrentNode::for_each(&self.root, &mut key, &mut |atoms, value| {
+            trie.set(K::from_atoms(atoms).borrow(), value.clone());
+        });
+        trie
+    }
+}
+
+impl<K: TrieKey, V> Default for ConcurrentTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Ord + Clone, V> ConcurrentNode<A, V> {
+    fn with_prefix(prefix: Vec<A>, value: Option<V>) -> Self {
+        ConcurrentNode {
+            prefix,
+            children: Vec::new(),
+            value,
+        }
+    }
+
+    /// finds the child whose first unit key starts with,
+    /// or where a child starting with key would go
+    fn child_index(&self, key: &[A]) -> Result<usize, usize> {
+        self.children
+            .binary_search_by(|(unit, _)| unit.as_slice().cmp(&key[..unit.len().min(key.len())]))
+    }
+
+    fn child(&self, key: &[A]) -> Option<&RwLock<Self>> {
+        let idx = self.child_index(key).ok()?;
+        Some(&self.children[idx].1)
+    }
+
+    fn add_child<K: TrieKey<Atom = A>>(&mut self, child: Self) {
+        let unit = first_unit::<K>(&child.prefix).to_vec();
+        self.insert_child(unit, Box::new(RwLock::new(child)));
+    }
+
+    fn insert_child(&mut self, unit: Vec<A>, child: Box<RwLock<Self>>) {
+        let idx = self.child_index(&unit).unwrap_err();
+        self.children.insert(idx, (unit, child));
+    }
+
+    /// key includes the prefix of the node
+    fn get_with<R, F: FnOnce(&V) -> R>(lock: &RwLock<Self>, key: &[A], f: F) -> Option<R> {
+        let node = read(lock);
+        let rest = key.strip_prefix(node.prefix.as_slice())?;
+        if rest.is_empty() {
+            return node.value.as_ref().map(f);
+        }
+        // the guard on this node keeps the child in place until we are done
+        ConcurrentNode::get_with(node.child(rest)?, rest, f)
+    }
+
+    /// key includes the prefix of the node
+    ///
+    /// returns the previous value, or gives value back
+    /// if key leaves the node's prefix, for the parent to split it.
+    fn set<K: TrieKey<Atom = A>>(
+        lock: &RwLock<Self>,
+        key: &[A],
+        mut value: V,
+    ) -> Result<Option<V>, V> {
+        {
+            let node = read(lock);
+            let Some(rest) = key.strip_prefix(node.prefix.as_slice()) else {
+                return Err(value);
+            };
+            if let Some(child) = node.child(rest) {
+                match ConcurrentNode::set::<K>(child, rest, value) {
+                    Ok(old) => return Ok(old),
+                    Err(back) => value = back,
+                }
+            }
+        }
+        // this node changes. our prefix can not, since we hold our parent's
+        // read lock, but the children may have changed meanwhile.
+        let mut node = write(lock);
+        let depth = node.prefix.len();
+        let rest = &key[depth..];
+        if rest.is_empty() {
+            return Ok(node.value.replace(value));
+        }
+        let Ok(idx) = node.child_index(rest) else {
+            node.add_child::<K>(ConcurrentNode::with_prefix(rest.to_vec(), Some(value)));
+            return Ok(None);
+        };
+        let slot = &mut node.children[idx].1;
+        let common = common_prefix_len(&exclusive(slot).prefix, rest);
+        if common == exclusive(slot).prefix.len() {
+            return ConcurrentNode::set::<K>(slot, rest, value);
+        }
+        // shares the first unit of a leaf's prefix, split the leaf where
+        // the last whole unit of the common part ends
+        let common = unit_floor::<K>(rest, common);
+        let mut split = ConcurrentNode::with_prefix(rest[..common].to_vec(), None);
+        if common == rest.len() {
+            split.value = Some(value);
+        } else {
+            split.add_child::<K>(ConcurrentNode::with_prefix(
+                rest[common..].to_vec(),
+                Some(value),
+            ));
+        }
+        let mut moved = std::mem::replace(slot, Box::new(RwLock::new(split)));
+        let moved_node = exclusive(&mut moved);
+        moved_node.prefix.drain(..common);
+        let unit = first_unit::<K>(&moved_node.prefix).to_vec();
+        exclusive(slot).insert_child(unit, moved);
+        Ok(None)
+    }
+
+    /// key includes the prefix of the node
+    ///
+    /// returns the removed value, and if the node may need compacting.
+    fn remove(lock: &RwLock<Self>, key: &[A]) -> Option<(V, bool)> {
+        let (value, rest) = {
+            let node = read(lock);
+            let rest = key.strip_prefix(node.prefix.as_slice())?;
+            // only the value changes, which our own lock covers
+            if rest.is_empty() {
+                drop(node);
+                let mut node = write(lock);
+                let value = node.value.take()?;
+                return Some((value, node.children.len() < 2));
+            }
+            let (value, tidy) = ConcurrentNode::remove(node.child(rest)?, rest)?;
+            if !tidy {
+                return Some((value, false));
+            }
+            (value, rest)
+        };
+        // the child lost a value, fold it away while nobody is below us
+        let mut node = write(lock);
+        if let Ok(idx) = node.child_index(rest) {
+            node.compact_child(idx);
+        }
+        Some((value, node.value.is_none() && node.children.len() < 2))
+    }
+
+    /// restores compression of a child after a removal
+    ///
+    /// valueless children vanish if they have no children of their own,
+    /// and take their child in if they have one.
+    fn compact_child(&mut self, idx: usize) {
+        let child = exclusive(&mut self.children[idx].1);
+        if child.value.is_some() || child.children.len() > 1 {
+            return;
+        }
+        match child.children.pop() {
+            None => {
+                self.children.remove(idx);
+            }
+            Some((_, grandchild)) => {
+                let grandchild = grandchild
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner);
+                child.prefix.extend(grandchild.prefix);
+                child.children = grandchild.children;
+                child.value = grandchild.value;
+            }
+        }
+    }
+
+    /// calls f with every key and value below lock, in key order
+    fn for_each<F: FnMut(&[A], &V)>(lock: &RwLock<Self>, key: &mut Vec<A>, f: &mut F) {
+        let node = read(lock);
+        let depth = key.len();
+        key.extend_from_slice(&node.prefix);
+        if let Some(value) = node.value.as_ref() {
+            f(key, value);
+        }
+        for (_, child) in node.children.iter() {
+            ConcurrentNode::for_each(child, key, f);
+        }
+        key.truncate(depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::Random;
+    use std::collections::BTreeMap;
+    use std::sync::Barrier;
+    use std::thread;
+
+    /// checks that every node below the root holds a value or branches
+    fn compressed(lock: &RwLock<ConcurrentNode<u8, u64>>, root: bool) -> bool {
+        let node = read(lock);
+        (root || node.value.is_some() || node.children.len() > 1)
+            && node.children.iter().all(|(unit, child)| {
+                read(child).prefix.starts_with(unit) && compressed(child, false)
+            })
+    }
+
+    #[test]
+    fn single_threaded() {
+        let trie: ConcurrentTrie<String, i32> = ConcurrentTrie::new();
+        assert_eq!(trie.set("abc", 1), None);
+        assert_eq!(trie.set("abd", 2), None);
+        assert_eq!(trie.set("ab", 3), None);
+        assert_eq!(trie.set("abc", 4), Some(1));
+        assert_eq!(trie.set("", 5), None);
+        assert_eq!(trie.len(), 4);
+        assert_eq!(trie.get("abc"), Some(4));
+        assert_eq!(trie.get("a"), None);
+        assert_eq!(trie.get_with("abd", |v| v * 10), Some(20));
+        assert_eq!(trie.remove("ab"), Some(3));
+        assert_eq!(trie.remove("ab"), None);
+        assert_eq!(trie.remove("abd"), Some(2));
+        // abc got folded back into a single node under the root
+        assert_eq!(read(&trie.root).children.len(), 1);
+        assert_eq!(
+            read(&read(&trie.root).children[0].1).prefix,
+            b"abc".to_vec()
+        );
+        let pairs: Vec<(String, i32)> = trie.snapshot().into_iter().collect();
+        assert_eq!(pairs, vec![("".into(), 5), ("abc".into(), 4)]);
+        assert!(!trie.is_empty());
+
+        // siblings part on their first character, even where they share a byte
+        let trie: ConcurrentTrie<String, i32> = ConcurrentTrie::new();
+        for (i, key) in ["café", "cafè", "caf", "日本", "日曜"].iter().enumerate() {
+            assert_eq!(trie.set(key, i as i32), None);
+        }
+        let root = read(&trie.root);
+        let caf = read(&root.children[0].1);
+        assert_eq!(caf.prefix, b"caf".to_vec());
+        let units: Vec<&[u8]> = caf.children.iter().map(|(unit, _)| &unit[..]).collect();
+        assert_eq!(units, vec!["è".as_bytes(), "é".as_bytes()]);
+        drop(caf);
+        drop(root);
+        assert_eq!(trie.get("cafè"), Some(1));
+        assert_eq!(trie.remove("café"), Some(0));
+        assert_eq!(trie.get("日曜"), Some(4));
+        let keys: Vec<String> = trie.snapshot().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["caf", "cafè", "日曜", "日本"]);
+    }
+
+    #[test]
+    fn writers_against_model() {
+        const THREADS: u64 = 8;
+        let trie: ConcurrentTrie<String, u64> = ConcurrentTrie::new();
+        let barrier = Barrier::new(THREADS as usize);
+        let models: Vec<BTreeMap<String, u64>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let (trie, barrier) = (&trie, &barrier);
+                    scope.spawn(move || {
+                        let mut random = Random(0x9e37_79b9_7f4a_7c15 ^ (t + 1));
+                        let mut model = BTreeMap::new();
+                        barrier.wait();
+                        for i in 0..20_000 {
+                            // threads own disjoint keys with shared prefixes,
+                            // so they keep splitting and merging the same nodes
+                            let key = format!("{:o}/{t}", random.next(512));
+                            match random.next(3) {
+                                0 => assert_eq!(trie.set(&key, i), model.insert(key, i)),
+                                1 => assert_eq!(trie.remove(&key), model.remove(&key)),
+                                _ => assert_eq!(trie.get(&key), model.get(&key).copied()),
+                            }
+                        }
+                        model
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        let expected: BTreeMap<String, u64> = models.into_iter().flatten().collect();
+        assert_eq!(trie.len(), expected.len());
+        let found: BTreeMap<String, u64> = trie.snapshot().into_iter().collect();
+        assert_eq!(found, expected);
+        assert!(compressed(&trie.root, true));
+    }
+
+    #[test]
+    fn readers_during_writes() {
+        let trie: ConcurrentTrie<String, usize> = ConcurrentTrie::new();
+        let stable: Vec<String> = (0..200).map(|i| format!("{i}")).collect();
+        for (i, key) in stable.iter().enumerate() {
+            trie.set(key, i);
+        }
+        thread::scope(|scope| {
+            for t in 0..4u64 {
+                let trie = &trie;
+                scope.spawn(move || {
+                    let mut random = Random(0x2545_f491_4f6c_dd1d ^ (t + 1));
+                    for _ in 0..20_000 {
+                        // neighbours of the stable keys, forcing splits above them
+                        let key = format!("{}{}", random.next(200), random.next(10));
+                        if random.next(2) == 0 {
+                            trie.set(&format!("{key}x"), 0);
+                        } else {
+                            trie.remove(&format!("{key}x"));
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let (trie, stable) = (&trie, &stable);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        for (i, key) in stable.iter().enumerate() {
+                            assert_eq!(trie.get(key), Some(i));
+                        }
+                    }
+                });
+            }
+        });
+        for (i, key) in stable.iter().enumerate() {
+            assert_eq!(trie.get(key), Some(i));
+        }
+    }
+}
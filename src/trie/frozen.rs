@@ -0,0 +1,686 @@
+//! read only prefix tree encoded into a single byte buffer
+//!
+//! the shape of the tree is stored level by level as a LOUDS bitvector:
+//! every node, in breadth first order, writes a one per child and a zero.
+//! node prefixes are concatenated into a label array, with a second bitvector
+//! marking where each one starts, and a third marks the nodes with a value.
+//! bitvectors carry a rank every 512 bits, so navigating is rank and select
+//! over the buffer, which is read in place without being decoded first.
+//!
+//! layout, all integers little endian:
+//! - magic, node count (u64), label length (u64)
+//! - shape, label start and value bitvectors, each as its length in bits
+//!   (u64), the bits in u64 words, then a u32 rank per 512 bits plus one
+//! - label bytes
+//! - values, u64 each
+
+use std::iter::FusedIterator;
+
+use super::{Trie, TrieKey};
+
+const MAGIC: &[u8; 8] = b"VCFTRIE1";
+
+/// bits per rank sample
+const BLOCK: usize = 512;
+
+/// a buffer that is not a frozen trie
+#[derive(Debug, Clone)]
+pub struct FrozenTrieError;
+
+impl std::fmt::Display for FrozenTrieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("buffer is not a frozen trie")
+    }
+}
+
+impl std::error::Error for FrozenTrieError {}
+
+/// read only prefix tree over a byte buffer, with byte string keys and u64 values
+///
+/// this is only a view, so it can sit on top of a file that was read or
+/// memory mapped. buffers are checked for sizes, rank samples and a tree
+/// shape when loaded, a corrupted one that passes may give wrong answers.
+#[derive(Debug, Clone, Copy)]
+pub struct FrozenTrie<'a> {
+    /// number of nodes, including the root
+    nodes: usize,
+    shape: Bits<'a>,
+    starts: Bits<'a>,
+    valued: Bits<'a>,
+    labels: &'a [u8],
+    values: &'a [u8],
+}
+
+impl<K: TrieKey<Atom = u8>, V> Trie<V, K> {
+    /// encodes this tree into the buffer a [`FrozenTrie`] reads
+    ///
+    /// values are stored as the u64 that encode makes of them.
+    pub fn freeze<F: FnMut(&V) -> u64>(&self, mut encode: F) -> Vec<u8> {
+        let mut shape = BitsBuilder::default();
+        let mut starts = BitsBuilder::default();
+        let mut valued = BitsBuilder::default();
+        let mut labels = Vec::new();
+        let mut values = Vec::new();
+        // breadth first, children are numbered in the order they are queued
+        let mut queue = std::collections::VecDeque::from([&self.root]);
+        let mut nodes = 0;
+        while let Some(node) = queue.pop_front() {
+            nodes += 1;
+            for child in node.children.iter() {
+                shape.push(true);
+                for (i, atom) in child.prefix.iter().enumerate() {
+                    starts.push(i == 0);
+                    labels.push(*atom);
+                }
+                queue.push_back(child);
+            }
+            shape.push(false);
+            valued.push(node.value.is_some());
+            if let Some(value) = node.value.as_ref() {
+                values.extend_from_slice(&encode(value).to_le_bytes());
+            }
+        }
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&(nodes as u64).to_le_bytes());
+        out.extend_from_slice(&(labels.len() as u64).to_le_bytes());
+        shape.write(&mut out);
+        starts.write(&mut out);
+        valued.write(&mut out);
+        out.extend_from_slice(&labels);
+        out.extend_from_slice(&values);
+        out
+    }
+}
+
+impl<'a> FrozenTrie<'a> {
+    /// loads a buffer made by [`Trie::freeze`], without copying it
+    pub fn new(bytes: &'a [u8]) -> Result<Self, FrozenTrieError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(FrozenTrieError);
+        }
+        let nodes = reader.usize()?;
+        let label_len = reader.usize()?;
+        let shape = Bits::read(&mut reader)?;
+        let starts = Bits::read(&mut reader)?;
+        let valued = Bits::read(&mut reader)?;
+        let labels = reader.take(label_len)?;
+        let count = valued.rank1(valued.len);
+        let values = reader.take(count.checked_mul(8).ok_or(FrozenTrieError)?)?;
+        let edges = nodes.checked_sub(1).ok_or(FrozenTrieError)?;
+        let consistent = reader.0.is_empty()
+            && Some(shape.len) == edges.checked_add(nodes)
+            && shape.rank1(shape.len) == edges
+            && starts.len == label_len
+            && starts.rank1(starts.len) == edges
+            && valued.len == nodes;
+        if !consistent || !shape.is_tree(nodes) {
+            return Err(FrozenTrieError);
+        }
+        Ok(FrozenTrie {
+            nodes,
+            shape,
+            starts,
+            valued,
+            labels,
+            values,
+        })
+    }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len() / 8
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// gets the value of a key
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        let mut node = 0;
+        let mut rest = key;
+        while !rest.is_empty() {
+            node = self.child(node, rest)?;
+            rest = rest.strip_prefix(self.label(node))?;
+        }
+        self.value(node)
+    }
+
+    /// checks if a key exists
+    #[inline]
+    pub fn has(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// iterates over key/value pairs in lexicographic key order
+    pub fn iter(&self) -> FrozenIteration<'a> {
+        self.iter_prefix(&[])
+    }
+
+    /// iterates over key/value pairs whose keys start with prefix,
+    /// in lexicographic key order
+    pub fn iter_prefix(&self, prefix: &[u8]) -> FrozenIteration<'a> {
+        let mut node = 0;
+        let mut depth = 0;
+        // find the topmost node whose keys all start with prefix
+        while depth < prefix.len() {
+            let rest = &prefix[depth..];
+            let mut leading = self.leading(node, rest);
+            let Some(child) = leading.next() else {
+                return FrozenIteration::empty(*self);
+            };
+            let label = self.label(child);
+            if label.starts_with(rest) {
+                // a prefix ending inside of a character can lead into
+                // every sibling starting with that character's bytes
+                let mut stack: Vec<(usize, usize)> = std::iter::once(child)
+                    .chain(leading)
+                    .map(|child| (depth, child))
+                    .collect();
+                stack.reverse();
+                return FrozenIteration {
+                    trie: *self,
+                    stack,
+                    key: prefix[..depth].to_vec(),
+                };
+            }
+            if !rest.starts_with(label) {
+                return FrozenIteration::empty(*self);
+            }
+            depth += label.len();
+            node = child;
+        }
+        FrozenIteration {
+            trie: *self,
+            stack: vec![(depth, node)],
+            key: prefix[..depth].to_vec(),
+        }
+    }
+
+    /// finds the longest stored key that is a prefix of key
+    ///
+    /// returns that part of key and its value.
+    pub fn longest_prefix_match<'k>(&self, key: &'k [u8]) -> Option<(&'k [u8], u64)> {
+        let mut node = 0;
+        let mut depth = 0;
+        let mut best = self.value(node).map(|value| (0, value));
+        while depth < key.len() {
+            let Some(child) = self.child(node, &key[depth..]) else {
+                break;
+            };
+            let label = self.label(child);
+            if !key[depth..].starts_with(label) {
+                break;
+            }
+            depth += label.len();
+            node = child;
+            if let Some(value) = self.value(node) {
+                best = Some((depth, value));
+            }
+        }
+        best.map(|(depth, value)| (&key[..depth], value))
+    }
+
+    /// gets the first child and number of children of a node
+    fn children(&self, node: usize) -> (usize, usize) {
+        let start = match node {
+            0 => 0,
+            _ => self.shape.select0(node - 1) + 1,
+        };
+        let end = self.shape.select0(node);
+        // every node but the root is counted by a one before its parent's zero
+        (self.shape.rank1(start) + 1, end - start)
+    }
+
+    /// finds the child key leads into, whose label and key agree
+    /// up to the shorter of the two
+    fn child(&self, node: usize, key: &[u8]) -> Option<usize> {
+        self.leading(node, key).next()
+    }
+
+    /// iterates the children whose label and key agree up to the shorter
+    /// of the two, in order
+    ///
+    /// labels of string keys end on character boundaries, so siblings can
+    /// share their first byte. only a key ending inside of a character
+    /// leads into more than one of them.
+    fn leading<'k>(&self, node: usize, key: &'k [u8]) -> impl Iterator<Item = usize> + 'k
+    where
+        'a: 'k,
+    {
+        let (first, count) = self.children(node);
+        let (mut low, mut high) = (first, first + count);
+        // the first child starting with key's first atom or later
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.labels[self.starts.select1(mid - 1)] < key[0] {
+                true => low = mid + 1,
+                false => high = mid,
+            }
+        }
+        let trie = *self;
+        (low..first + count)
+            .take_while(move |child| trie.labels[trie.starts.select1(child - 1)] == key[0])
+            .filter(move |child| {
+                let label = trie.label(*child);
+                let len = label.len().min(key.len());
+                label[..len] == key[..len]
+            })
+    }
+
+    fn label(&self, node: usize) -> &'a [u8] {
+        if node == 0 {
+            return &[];
+        }
+        let start = self.starts.select1(node - 1);
+        let end = match node + 1 < self.nodes {
+            true => self.starts.select1(node),
+            false => self.labels.len(),
+        };
+        &self.labels[start..end]
+    }
+
+    fn value(&self, node: usize) -> Option<u64> {
+        if !self.valued.get(node) {
+            return None;
+        }
+        let at = self.valued.rank1(node) * 8;
+        Some(u64::from_le_bytes(
+            self.values[at..at + 8].try_into().unwrap(),
+        ))
+    }
+}
+
+/// key ordered iteration over a frozen trie
+pub struct FrozenIteration<'a> {
+    trie: FrozenTrie<'a>,
+    /// nodes left to visit, with the length of the key leading up to them
+    stack: Vec<(usize, usize)>,
+    /// key of the last node visited
+    key: Vec<u8>,
+}
+
+impl<'a> FrozenIteration<'a> {
+    fn empty(trie: FrozenTrie<'a>) -> Self {
+        FrozenIteration {
+            trie,
+            stack: Vec::new(),
+            key: Vec::new(),
+        }
+    }
+}
+
+impl Iterator for FrozenIteration<'_> {
+    type Item = (Vec<u8>, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            self.key.truncate(depth);
+            self.key.extend_from_slice(self.trie.label(node));
+            let depth = self.key.len();
+            let (first, count) = self.trie.children(node);
+            self.stack
+                .extend((first..first + count).rev().map(|child| (depth, child)));
+            if let Some(value) = self.trie.value(node) {
+                return Some((self.key.clone(), value));
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for FrozenIteration<'_> {}
+
+/// bitvector in a buffer, with rank samples
+#[derive(Debug, Clone, Copy)]
+struct Bits<'a> {
+    /// length in bits
+    len: usize,
+    /// u64 words
+    words: &'a [u8],
+    /// u32 number of ones before every block
+    ranks: &'a [u8],
+}
+
+impl<'a> Bits<'a> {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, FrozenTrieError> {
+        let len = reader.usize()?;
+        // sizes come from the buffer, so may be anything
+        let words = len.div_ceil(64).checked_mul(8).ok_or(FrozenTrieError)?;
+        let words = reader.take(words)?;
+        let ranks = (len / BLOCK)
+            .checked_add(1)
+            .and_then(|blocks| blocks.checked_mul(4))
+            .ok_or(FrozenTrieError)?;
+        let ranks = reader.take(ranks)?;
+        let bits = Bits { len, words, ranks };
+        // rank and select trust the samples, so they have to add up
+        let mut count = 0;
+        for block in 0..bits.blocks() {
+            if bits.rank(block) != count {
+                return Err(FrozenTrieError);
+            }
+            let end = ((block + 1) * BLOCK / 64).min(len.div_ceil(64));
+            for word in block * BLOCK / 64..end {
+                count += bits.word(word).count_ones() as usize;
+            }
+        }
+        // and bits past the end are not counted
+        if !len.is_multiple_of(64) && bits.word(len / 64) >> (len % 64) != 0 {
+            return Err(FrozenTrieError);
+        }
+        Ok(bits)
+    }
+
+    /// checks if these are the shape of a tree of nodes
+    ///
+    /// every node below the root has its one before the zero ending its
+    /// own children, so children come after their parent.
+    fn is_tree(&self, nodes: usize) -> bool {
+        let (mut ones, mut zeros) = (0, 0);
+        for i in 0..self.len {
+            match self.get(i) {
+                true => ones += 1,
+                false => zeros += 1,
+            }
+            if zeros < nodes && ones < zeros {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn word(&self, i: usize) -> u64 {
+        u64::from_le_bytes(self.words[i * 8..i * 8 + 8].try_into().unwrap())
+    }
+
+    fn rank(&self, block: usize) -> usize {
+        u32::from_le_bytes(self.ranks[block * 4..block * 4 + 4].try_into().unwrap()) as usize
+    }
+
+    fn blocks(&self) -> usize {
+        self.ranks.len() / 4
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.word(i / 64) >> (i % 64) & 1 == 1
+    }
+
+    /// counts the ones before position i
+    fn rank1(&self, i: usize) -> usize {
+        let block = i / BLOCK;
+        let mut count = self.rank(block);
+        for word in block * BLOCK / 64..i / 64 {
+            count += self.word(word).count_ones() as usize;
+        }
+        if !i.is_multiple_of(64) {
+            count += (self.word(i / 64) & ((1 << (i % 64)) - 1)).count_ones() as usize;
+        }
+        count
+    }
+
+    /// finds the position of the one with rank k
+    fn select1(&self, k: usize) -> usize {
+        self.select(k, |block| self.rank(block), |word| word)
+    }
+
+    /// finds the position of the zero with rank k
+    fn select0(&self, k: usize) -> usize {
+        self.select(k, |block| block * BLOCK - self.rank(block), |word| !word)
+    }
+
+    /// finds the bit with rank k, given how many come before every block
+    /// and how to turn a word into those bits
+    fn select<R, W>(&self, k: usize, before: R, bits: W) -> usize
+    where
+        R: Fn(usize) -> usize,
+        W: Fn(u64) -> u64,
+    {
+        // last block with at most k bits before it
+        let (mut low, mut high) = (0, self.blocks());
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match before(mid) <= k {
+                true => low = mid,
+                false => high = mid,
+            }
+        }
+        let mut left = k - before(low);
+        let mut word = low * BLOCK / 64;
+        loop {
+            let mut current = bits(self.word(word));
+            let count = current.count_ones() as usize;
+            if left < count {
+                for _ in 0..left {
+                    current &= current - 1;
+                }
+                return word * 64 + current.trailing_zeros() as usize;
+            }
+            left -= count;
+            word += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitsBuilder {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitsBuilder {
+    fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        self.words[self.len / 64] |= u64::from(bit) << (self.len % 64);
+        self.len += 1;
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.len as u64).to_le_bytes());
+        for word in self.words.iter() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        let mut count: u32 = 0;
+        for block in 0..=self.len / BLOCK {
+            out.extend_from_slice(&count.to_le_bytes());
+            let words = self.words.iter().skip(block * BLOCK / 64).take(BLOCK / 64);
+            count += words.map(|word| word.count_ones()).sum::<u32>();
+        }
+    }
+}
+
+/// reads sections off the front of a buffer
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FrozenTrieError> {
+        if len > self.0.len() {
+            return Err(FrozenTrieError);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn usize(&mut self) -> Result<usize, FrozenTrieError> {
+        let bytes = self.take(8)?.try_into().unwrap();
+        usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| FrozenTrieError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::{pairs, words};
+
+    /// keyed by bytes, like the frozen trie
+    fn dictionary() -> Trie<u64, Vec<u8>> {
+        let words = words(&[
+            "/",
+            "/api",
+            "/api/v1",
+            "/api/v2",
+            "/static",
+            "a",
+            "an",
+            "and",
+            "ant",
+            "anthem",
+            "banana",
+            "band",
+            "bandana",
+            "cafè",
+            "café",
+            "can",
+            "cane",
+            "日曜",
+            "日本",
+            "日本語",
+        ]);
+        words
+            .into_iter()
+            .map(|(word, i)| (word.into_bytes(), i as u64 * 3))
+            .collect()
+    }
+
+    #[test]
+    fn matches_source_trie() {
+        let trie = dictionary();
+        let bytes = trie.freeze(|value| *value);
+        // one byte in, so nothing relies on the buffer's alignment
+        let mut shifted = vec![0];
+        shifted.extend_from_slice(&bytes);
+        let frozen = FrozenTrie::new(&shifted[1..]).unwrap();
+        assert_eq!(frozen.len(), trie.keys().count());
+        for (key, value) in trie.iter() {
+            assert_eq!(frozen.get(&key), Some(*value));
+        }
+        for missing in ["", "b", "ban", "bandanas", "/api/", "cafê", "日", "z"] {
+            assert!(!frozen.has(missing.as_bytes()), "{missing}");
+        }
+        for prefix in [
+            "", "/", "/a", "/api/", "an", "ant", "ban", "band", "c", "caf", "café", "x", "日本",
+            "日",
+        ] {
+            let found: Vec<_> = frozen.iter_prefix(prefix.as_bytes()).collect();
+            assert_eq!(
+                found,
+                pairs(trie.iter_prefix(prefix.as_bytes())),
+                "{prefix}"
+            );
+        }
+        // bytes ending inside of a character lead into every sibling starting with them
+        let found: Vec<_> = frozen.iter_prefix(&"café".as_bytes()[..4]).collect();
+        assert_eq!(found, pairs(trie.iter_prefix(b"caf")));
+        for key in [
+            "/api/v1/users",
+            "/api/v3",
+            "/stat",
+            "api",
+            "anthems",
+            "bandit",
+            "日本語です",
+        ] {
+            let expected = trie
+                .longest_prefix_match(key.as_bytes())
+                .map(|(key, value)| (key, *value));
+            assert_eq!(
+                frozen.longest_prefix_match(key.as_bytes()),
+                expected,
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_and_root_values() {
+        let mut trie: Trie<u64, Vec<u8>> = Trie::empty();
+        let bytes = trie.freeze(|value| *value);
+        let frozen = FrozenTrie::new(&bytes).unwrap();
+        assert!(frozen.is_empty());
+        assert_eq!(frozen.iter().next(), None);
+        assert_eq!(frozen.longest_prefix_match(b"abc"), None);
+        trie.set(&[], 7);
+        trie.set(&[1, 2], 8);
+        let bytes = trie.freeze(|value| *value);
+        let frozen = FrozenTrie::new(&bytes).unwrap();
+        assert_eq!(frozen.get(&[]), Some(7));
+        assert_eq!(frozen.longest_prefix_match(&[1, 3]), Some((&[][..], 7)));
+        assert_eq!(
+            frozen.iter().collect::<Vec<_>>(),
+            vec![(vec![], 7), (vec![1, 2], 8)]
+        );
+    }
+
+    #[test]
+    fn many_blocks() {
+        // enough nodes for every bitvector to span several rank blocks
+        let mut trie: Trie<u64, Vec<u8>> = Trie::empty();
+        for i in 0..5000u64 {
+            trie.set((i * 7919 % 100_003).to_string().as_bytes(), i);
+        }
+        let bytes = trie.freeze(|value| *value);
+        let frozen = FrozenTrie::new(&bytes).unwrap();
+        let expected = pairs(trie.iter());
+        assert_eq!(frozen.iter().collect::<Vec<_>>(), expected);
+        for (key, value) in expected.iter() {
+            assert_eq!(frozen.get(key), Some(*value));
+        }
+        assert_eq!(frozen.get(b"100003"), None);
+    }
+
+    #[test]
+    fn rejects_broken_buffers() {
+        let bytes = dictionary().freeze(|value| *value);
+        assert!(FrozenTrie::new(&bytes[..bytes.len() - 1]).is_err());
+        assert!(FrozenTrie::new(&bytes[1..]).is_err());
+        assert!(FrozenTrie::new(&[]).is_err());
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(FrozenTrie::new(&longer).is_err());
+        let mut miscounted = bytes.clone();
+        miscounted[8] += 1;
+        assert!(FrozenTrie::new(&miscounted).is_err());
+        // sizes that overflow once scaled
+        let header = |at: usize| {
+            let mut huge = bytes.clone();
+            huge[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            huge
+        };
+        // node count, then the length of the shape bits
+        assert!(FrozenTrie::new(&header(8)).is_err());
+        assert!(FrozenTrie::new(&header(24)).is_err());
+    }
+
+    #[test]
+    fn rejects_flipped_bits() {
+        let trie = dictionary();
+        let bytes = trie.freeze(|value| *value);
+        for bit in 0..bytes.len() * 8 {
+            let mut flipped = bytes.clone();
+            flipped[bit / 8] ^= 1 << (bit % 8);
+            // a flip is caught, or still reads as some tree
+            if let Ok(frozen) = FrozenTrie::new(&flipped) {
+                for (key, _) in frozen.iter() {
+                    frozen.get(&key);
+                }
+            }
+        }
+        // the second rank sample of a shape spanning several blocks
+        let mut trie: Trie<u64, Vec<u8>> = Trie::empty();
+        for i in 0..2000u64 {
+            trie.set((i * 7919 % 100_003).to_string().as_bytes(), i);
+        }
+        let mut bytes = trie.freeze(|value| *value);
+        let words = FrozenTrie::new(&bytes).unwrap().shape.words.len();
+        // after magic, two counts and the length of the shape bits
+        bytes[32 + words + 4] ^= 1;
+        assert!(FrozenTrie::new(&bytes).is_err());
+    }
+}
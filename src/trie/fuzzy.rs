@@ -0,0 +1,307 @@
+//! typo tolerant lookups by edit distance
+//!
+//! walks the tree keeping one row of the edit distance matrix per unit of
+//! the key walked so far, and drops a branch once no row entry is in range.
+//! a unit is what an edit counts as one, a character for `String` keys and
+//! a single atom otherwise. node prefixes are made of whole units.
+
+use std::iter::FusedIterator;
+
+use super::{Trie, TrieKey, TrieNode};
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// iterates over keys within max_distance levenshtein edits of query,
+    /// in lexicographic key order
+    ///
+    /// yields the key, its value and its distance.
+    /// distances count characters for `String` keys, atoms otherwise.
+    pub fn fuzzy<'q>(&self, query: &'q K::Slice, max_distance: usize) -> Fuzzy<'_, 'q, K, V> {
+        Fuzzy::new(&self.root, K::atoms(query), max_distance, false)
+    }
+
+    /// like [`Trie::fuzzy`], but swapping two adjacent units counts as one edit
+    ///
+    /// this is the optimal string alignment flavour of damerau-levenshtein.
+    pub fn fuzzy_damerau<'q>(
+        &self,
+        query: &'q K::Slice,
+        max_distance: usize,
+    ) -> Fuzzy<'_, 'q, K, V> {
+        Fuzzy::new(&self.root, K::atoms(query), max_distance, true)
+    }
+}
+
+/// key ordered iteration over keys close to a query
+pub struct Fuzzy<'a, 'q, K: TrieKey, V> {
+    /// the query, split into units
+    query: Vec<&'q [K::Atom]>,
+    max_distance: usize,
+    /// count adjacent swaps as one edit
+    transpositions: bool,
+    stack: Vec<Step<'a, K::Atom, V>>,
+    /// key of the last node visited
+    key: Vec<K::Atom>,
+    /// where the units of key start
+    units: Vec<usize>,
+}
+
+/// a node left to visit
+struct Step<'a, A, V> {
+    /// length of the key leading up to the node
+    depth: usize,
+    node: &'a TrieNode<A, V>,
+    /// distance row of the key leading up to the node
+    row: Vec<usize>,
+    /// the row before that, empty at the root
+    previous: Vec<usize>,
+}
+
+impl<'a, 'q, K: TrieKey, V> Fuzzy<'a, 'q, K, V> {
+    fn new(
+        root: &'a TrieNode<K::Atom, V>,
+        query: &'q [K::Atom],
+        max_distance: usize,
+        transpositions: bool,
+    ) -> Self {
+        let mut units = Vec::new();
+        let mut rest = query;
+        while let Some(first) = rest.first() {
+            let (unit, next) = rest.split_at(K::unit_len(first).min(rest.len()));
+            units.push(unit);
+            rest = next;
+        }
+        Fuzzy {
+            max_distance,
+            transpositions,
+            stack: vec![Step {
+                depth: 0,
+                node: root,
+                // the empty key is as far from the query as the query is long
+                row: (0..=units.len()).collect(),
+                previous: Vec::new(),
+            }],
+            query: units,
+            key: Vec::new(),
+            units: Vec::new(),
+        }
+    }
+
+    /// computes the row of the key, from the rows before its last unit
+    fn advance(&self, row: &[usize], previous: &[usize]) -> Vec<usize> {
+        let unit = &self.key[*self.units.last().unwrap()..];
+        let mut next = Vec::with_capacity(row.len());
+        next.push(row[0] + 1);
+        for (j, wanted) in self.query.iter().enumerate() {
+            let substitution = row[j] + usize::from(*wanted != unit);
+            let mut distance = substitution.min(row[j + 1] + 1).min(next[j] + 1);
+            if self.transpositions && j > 0 && !previous.is_empty() {
+                let at = self.units.len() - 2;
+                let last = &self.key[self.units[at]..self.units[at + 1]];
+                if unit == self.query[j - 1] && last == *wanted {
+                    distance = distance.min(previous[j - 1] + 1);
+                }
+            }
+            next.push(distance);
+        }
+        next
+    }
+
+    /// checks if no key continuing from these rows can come in range
+    fn hopeless(&self, row: &[usize], previous: &[usize]) -> bool {
+        let best = row.iter().min().unwrap();
+        if *best <= self.max_distance {
+            return false;
+        }
+        // a swap reaches back a row, and costs an edit on top of it
+        !self.transpositions
+            || previous
+                .iter()
+                .min()
+                .is_none_or(|best| *best >= self.max_distance)
+    }
+}
+
+impl<'a, 'q, K: TrieKey, V> Iterator for Fuzzy<'a, 'q, K, V> {
+    type Item = (K, &'a V, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        'nodes: while let Some(Step {
+            depth,
+            node,
+            mut row,
+            mut previous,
+        }) = self.stack.pop()
+        {
+            self.key.truncate(depth);
+            let units = self.units.partition_point(|start| *start < depth);
+            self.units.truncate(units);
+            let mut rest = &node.prefix[..];
+            while let Some(first) = rest.first() {
+                let (unit, after) = rest.split_at(K::unit_len(first));
+                rest = after;
+                self.units.push(self.key.len());
+                self.key.extend_from_slice(unit);
+                let next = self.advance(&row, &previous);
+                previous = std::mem::replace(&mut row, next);
+                if self.hopeless(&row, &previous) {
+                    continue 'nodes;
+                }
+            }
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.iter().rev().map(|child| Step {
+                    depth,
+                    node: child,
+                    row: row.clone(),
+                    previous: previous.clone(),
+                }));
+            let distance = row[self.query.len()];
+            if distance > self.max_distance {
+                continue;
+            }
+            if let Some(value) = node.value.as_ref() {
+                return Some((K::from_atoms(&self.key), value, distance));
+            }
+        }
+        None
+    }
+}
+
+impl<K: TrieKey, V> FusedIterator for Fuzzy<'_, '_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::words;
+
+    /// full matrix reference implementation
+    fn distance<T: PartialEq>(a: &[T], b: &[T], transpositions: bool) -> usize {
+        let mut matrix = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..=a.len() {
+            for j in 0..=b.len() {
+                matrix[i][j] = if i == 0 || j == 0 {
+                    i + j
+                } else {
+                    let mut best = (matrix[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                        .min(matrix[i - 1][j] + 1)
+                        .min(matrix[i][j - 1] + 1);
+                    if transpositions
+                        && i > 1
+                        && j > 1
+                        && a[i - 1] == b[j - 2]
+                        && a[i - 2] == b[j - 1]
+                    {
+                        best = best.min(matrix[i - 2][j - 2] + 1);
+                    }
+                    best
+                };
+            }
+        }
+        matrix[a.len()][b.len()]
+    }
+
+    fn dictionary() -> Trie<usize> {
+        words(&[
+            "apple",
+            "apply",
+            "ample",
+            "maple",
+            "applet",
+            "app",
+            "ape",
+            "apples",
+            "banana",
+            "bandana",
+            "cabana",
+            "an",
+            "a",
+            "",
+            "paple",
+            "pale",
+            "lapel",
+            "plea",
+            "naïve",
+            "café",
+            "日本語",
+            "日本人",
+            "éa",
+        ])
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let trie = dictionary();
+        for transpositions in [false, true] {
+            for query in [
+                "apple", "aplpe", "banan", "", "x", "pplea", "cabbana", "naive", "cafe", "日本",
+                "本日", "aé", "ï",
+            ] {
+                for max in 0..4 {
+                    let found: Vec<(String, usize)> = if transpositions {
+                        trie.fuzzy_damerau(query, max)
+                            .map(|(key, _, d)| (key, d))
+                            .collect()
+                    } else {
+                        trie.fuzzy(query, max).map(|(key, _, d)| (key, d)).collect()
+                    };
+                    let expected: Vec<(String, usize)> = trie
+                        .keys()
+                        .map(|key| {
+                            let key_chars: Vec<char> = key.chars().collect();
+                            let query_chars: Vec<char> = query.chars().collect();
+                            let d = distance(&key_chars, &query_chars, transpositions);
+                            (key, d)
+                        })
+                        .filter(|(_, d)| *d <= max)
+                        .collect();
+                    assert_eq!(found, expected, "{query} within {max}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transpositions() {
+        let trie = dictionary();
+        let plain: Vec<(String, usize)> = trie.fuzzy("aplpe", 1).map(|(k, _, d)| (k, d)).collect();
+        assert!(plain.is_empty());
+        let swapped: Vec<(String, usize)> = trie
+            .fuzzy_damerau("aplpe", 1)
+            .map(|(k, _, d)| (k, d))
+            .collect();
+        assert_eq!(swapped, vec![("apple".to_owned(), 1)]);
+    }
+
+    #[test]
+    fn characters() {
+        let trie = dictionary();
+        let found = |query, max| -> Vec<(String, usize)> {
+            trie.fuzzy(query, max).map(|(k, _, d)| (k, d)).collect()
+        };
+        // a changed character is one edit, however many bytes it takes
+        assert_eq!(found("naive", 1), vec![("naïve".to_owned(), 1)]);
+        assert_eq!(found("cafe", 1), vec![("café".to_owned(), 1)]);
+        assert_eq!(
+            found("日本", 1),
+            vec![("日本人".to_owned(), 1), ("日本語".to_owned(), 1)]
+        );
+        assert_eq!(found("日本和", 1).len(), 2);
+        let swapped: Vec<(String, usize)> = trie
+            .fuzzy_damerau("aé", 1)
+            .map(|(k, _, d)| (k, d))
+            .collect();
+        assert_eq!(
+            swapped,
+            vec![
+                ("a".to_owned(), 1),
+                ("an".to_owned(), 1),
+                ("éa".to_owned(), 1)
+            ]
+        );
+        // sequence keys count atoms
+        let mut bytes: Trie<(), Vec<u8>> = Trie::empty();
+        bytes.set(&[0xc3, 0xa9], ());
+        assert_eq!(bytes.fuzzy(&[0xc3, 0xa8], 1).count(), 1);
+        assert_eq!(bytes.fuzzy(&[0x65], 1).count(), 0);
+    }
+}
@@ -0,0 +1,285 @@
+//! wildcard queries over string keys
+//!
+//! patterns support `?` for any single character, `*` for any run of
+//! characters and classes like `[a-c]`, `[abc]` or `[!a-c]`.
+//! a backslash matches the character after it literally,
+//! a `[` without a closing `]` is taken literally as well.
+//!
+//! the pattern is compiled into a list of tokens, and matched by tracking
+//! the set of token positions reachable after each character of the key.
+//! a branch of the tree is dropped once that set runs empty.
+
+use std::iter::FusedIterator;
+
+use super::{Trie, TrieKey, TrieNode};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`, as inclusive ranges
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    /// checks if a single character is accepted by this token
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Token::Char(wanted) => *wanted == c,
+            Token::Any | Token::Star => true,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(low, high)| (*low..=*high).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let token = match chars[i] {
+            '?' => Token::Any,
+            // runs of stars match the same as one
+            '*' if tokens.last() == Some(&Token::Star) => {
+                i += 1;
+                continue;
+            }
+            '*' => Token::Star,
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                Token::Char(chars[i])
+            }
+            '[' => match class(&chars[i + 1..]) {
+                Some((token, used)) => {
+                    i += used;
+                    token
+                }
+                None => Token::Char('['),
+            },
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    tokens
+}
+
+/// parses a class from just past its `[`
+///
+/// returns the class and the number of characters it used, including the `]`.
+fn class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    let mut i = usize::from(negated);
+    let mut ranges = Vec::new();
+    // a leading `]` is a member, not the end
+    let mut first = true;
+    loop {
+        let low = *chars.get(i)?;
+        if low == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(high)) if *high != ']' => {
+                ranges.push((low, *high));
+                i += 3;
+            }
+            _ => {
+                ranges.push((low, low));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// set of token positions a key prefix can be at
+#[derive(Clone)]
+struct States(Vec<bool>);
+
+impl States {
+    fn start(tokens: &[Token]) -> Self {
+        let mut states = States(vec![false; tokens.len() + 1]);
+        states.enter(tokens, 0);
+        states
+    }
+
+    /// marks a position reachable, along with what a star lets it skip to
+    fn enter(&mut self, tokens: &[Token], mut position: usize) {
+        loop {
+            self.0[position] = true;
+            match tokens.get(position) {
+                Some(Token::Star) => position += 1,
+                _ => return,
+            }
+        }
+    }
+
+    /// moves every position past a character
+    fn step(&self, tokens: &[Token], c: char) -> Self {
+        let mut next = States(vec![false; tokens.len() + 1]);
+        for (position, token) in tokens.iter().enumerate() {
+            if !self.0[position] || !token.accepts(c) {
+                continue;
+            }
+            match token {
+                // a star takes the character and stays
+                Token::Star => next.enter(tokens, position),
+                _ => next.enter(tokens, position + 1),
+            }
+        }
+        next
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.0.contains(&true)
+    }
+
+    fn accepting(&self) -> bool {
+        *self.0.last().unwrap()
+    }
+}
+
+impl<V> Trie<V> {
+    /// iterates over keys matching a wildcard pattern, in lexicographic key order
+    ///
+    /// `?` matches any single character, `*` any run of characters,
+    /// and `[a-c]` any character of a class, `[!a-c]` any character outside it.
+    pub fn matches(&self, pattern: &str) -> Matches<'_, V> {
+        let tokens = compile(pattern);
+        let states = States::start(&tokens);
+        Matches {
+            tokens,
+            stack: vec![(0, &self.root, states)],
+            key: Vec::new(),
+        }
+    }
+}
+
+/// key ordered iteration over keys matching a pattern
+pub struct Matches<'a, V> {
+    tokens: Vec<Token>,
+    /// nodes left to visit, with the length of the key leading up to them
+    /// and the states the key left the pattern in
+    stack: Vec<(usize, &'a TrieNode<u8, V>, States)>,
+    /// key of the last node visited
+    key: Vec<u8>,
+}
+
+impl<'a, V> Iterator for Matches<'a, V> {
+    type Item = (String, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        'nodes: while let Some((depth, node, mut states)) = self.stack.pop() {
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.prefix);
+            // prefixes are made of whole characters
+            for c in std::str::from_utf8(&node.prefix).unwrap().chars() {
+                states = states.step(&self.tokens, c);
+                if states.is_empty() {
+                    continue 'nodes;
+                }
+            }
+            let depth = self.key.len();
+            self.stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|child| (depth, child, states.clone())),
+            );
+            if !states.accepting() {
+                continue;
+            }
+            if let Some(value) = node.value.as_ref() {
+                return Some((String::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<V> FusedIterator for Matches<'_, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::words;
+
+    fn config() -> Trie<usize> {
+        words(&[
+            "service.api.timeout",
+            "service.api.retries",
+            "service.db.timeout",
+            "service.db.pool.timeout",
+            "service.cache.timeout",
+            "services.timeout",
+            "logging.level",
+            "a1",
+            "b2",
+            "c3",
+            "d4",
+            "naïve",
+            "naive",
+            "日本語",
+        ])
+    }
+
+    fn keys(trie: &Trie<usize>, pattern: &str) -> Vec<String> {
+        trie.matches(pattern).map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        let trie = config();
+        assert_eq!(
+            keys(&trie, "service.*.timeout"),
+            vec![
+                "service.api.timeout",
+                "service.cache.timeout",
+                "service.db.pool.timeout",
+                "service.db.timeout",
+            ]
+        );
+        assert_eq!(
+            keys(&trie, "service.??.timeout"),
+            vec!["service.db.timeout"]
+        );
+        assert_eq!(keys(&trie, "*level"), vec!["logging.level"]);
+        assert_eq!(keys(&trie, "logging.level"), vec!["logging.level"]);
+        assert_eq!(keys(&trie, "logging.leve"), Vec::<String>::new());
+        assert_eq!(keys(&trie, "*").len(), 14);
+        assert_eq!(keys(&trie, "**.timeout").len(), 5);
+    }
+
+    #[test]
+    fn classes() {
+        let trie = config();
+        assert_eq!(keys(&trie, "[a-c][0-9]"), vec!["a1", "b2", "c3"]);
+        assert_eq!(keys(&trie, "[!a-c][0-9]"), vec!["d4"]);
+        assert_eq!(keys(&trie, "[ad]?"), vec!["a1", "d4"]);
+        assert_eq!(keys(&trie, "[]a]1"), vec!["a1"]);
+        // unclosed, literal
+        assert_eq!(keys(&trie, "[a1"), Vec::<String>::new());
+        assert_eq!(
+            keys(&trie, "service\\.api.*"),
+            vec!["service.api.retries", "service.api.timeout"]
+        );
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        let trie = config();
+        assert_eq!(keys(&trie, "na?ve"), vec!["naive", "naïve"]);
+        assert_eq!(keys(&trie, "na[ï]ve"), vec!["naïve"]);
+        assert_eq!(keys(&trie, "???"), vec!["日本語"]);
+        assert_eq!(keys(&trie, "日*"), vec!["日本語"]);
+        assert_eq!(keys(&trie, "?本?"), vec!["日本語"]);
+    }
+}
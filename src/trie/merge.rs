@@ -0,0 +1,318 @@
+//! set algebra between tries
+//!
+//! both trees are walked in lock-step, with prefixes split where the last
+//! unit they share ends. subtrees found on only one side are kept or dropped whole, only
+//! nodes both sides lead to are looked into.
+
+use std::cmp::Ordering;
+
+use super::{
+    common_prefix_len, first_unit, unit_floor, Children, Trie, TrieAtom, TrieKey, TrieNode,
+};
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// merges the keys of other into ours
+    ///
+    /// keys on both sides get the value f makes of both values, ours first.
+    pub fn union_with<F: FnMut(V, V) -> V>(self, other: Self, mut f: F) -> Self {
+        self.merge(
+            other,
+            Merge::new(
+                |ours, theirs| Some(f(ours, theirs)),
+                true,
+                Some((|node| node, |value| value)),
+            ),
+        )
+    }
+
+    /// keeps only the keys other has as well
+    ///
+    /// their values are what f makes of both values, ours first.
+    pub fn intersection<W, F: FnMut(V, W) -> V>(self, other: Trie<W, K>, mut f: F) -> Self {
+        self.merge(
+            other,
+            Merge::new(|ours, theirs| Some(f(ours, theirs)), false, None),
+        )
+    }
+
+    /// keeps only the keys other does not have
+    pub fn difference<W>(self, other: Trie<W, K>) -> Self {
+        self.merge(other, Merge::new(|_, _| None, true, None))
+    }
+
+    /// keeps the keys only one of the two tries has
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        self.merge(
+            other,
+            Merge::new(|_, _| None, true, Some((|node| node, |value| value))),
+        )
+    }
+
+    fn merge<W, F>(self, other: Trie<W, K>, mut merge: Merge<K, V, W, F>) -> Self
+    where
+        F: FnMut(V, W) -> Option<V>,
+    {
+        // both roots have the empty prefix, and stay roots
+        let root = merge.nodes(self.root, other.root);
+        // keys on one side only are kept or dropped together with their subtree
+        let mut len = merge.kept;
+        if merge.left {
+            len += self.len - merge.met;
+        }
+        if merge.right.is_some() {
+            len += other.len - merge.met;
+        }
+        Trie { root, len }
+    }
+}
+
+/// what to do with keys depending on the side they are on
+struct Merge<K: TrieKey, V, W, F> {
+    /// resolves keys on both sides, None drops the key
+    both: F,
+    /// keep what only the left side has
+    left: bool,
+    /// keep what only the right side has
+    right: Option<Take<K::Atom, V, W>>,
+    /// number of keys found on both sides
+    met: usize,
+    /// number of those kept
+    kept: usize,
+}
+
+/// how to take over nodes and values of the right side
+type Take<A, V, W> = (fn(TrieNode<A, W>) -> TrieNode<A, V>, fn(W) -> V);
+
+impl<K: TrieKey, V, W, F> Merge<K, V, W, F>
+where
+    F: FnMut(V, W) -> Option<V>,
+{
+    fn new(both: F, left: bool, right: Option<Take<K::Atom, V, W>>) -> Self {
+        Merge {
+            both,
+            left,
+            right,
+            met: 0,
+            kept: 0,
+        }
+    }
+
+    /// merges two nodes whose prefixes start at the same key position
+    ///
+    /// the result is not compacted, the caller does that.
+    fn nodes(
+        &mut self,
+        mut left: TrieNode<K::Atom, V>,
+        mut right: TrieNode<K::Atom, W>,
+    ) -> TrieNode<K::Atom, V> {
+        let common = common_prefix_len(&left.prefix, &right.prefix);
+        let common = unit_floor::<K>(&left.prefix, common);
+        let prefix: Vec<K::Atom> = left.prefix.drain(..common).collect();
+        right.prefix.drain(..common);
+        // whatever is left of a prefix goes one level down
+        let (value, children) = match (left.prefix.is_empty(), right.prefix.is_empty()) {
+            (true, true) => (
+                self.values(left.value, right.value),
+                self.children(left.children, right.children),
+            ),
+            (true, false) => (
+                self.values(left.value, None),
+                self.children(left.children, single(right)),
+            ),
+            (false, true) => (
+                self.values(None, right.value),
+                self.children(single(left), right.children),
+            ),
+            (false, false) => (None, self.children(single(left), single(right))),
+        };
+        TrieNode {
+            prefix,
+            children,
+            value,
+        }
+    }
+
+    fn values(&mut self, left: Option<V>, right: Option<W>) -> Option<V> {
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                self.met += 1;
+                let value = (self.both)(left, right);
+                self.kept += usize::from(value.is_some());
+                value
+            }
+            (Some(left), None) => self.left.then_some(left),
+            (None, Some(right)) => self.right.map(|(_, take)| take(right)),
+            (None, None) => None,
+        }
+    }
+
+    /// merges two sets of children, both sorted by first unit
+    fn children(
+        &mut self,
+        left: Children<K::Atom, V>,
+        right: Children<K::Atom, W>,
+    ) -> Children<K::Atom, V> {
+        let mut merged = Children::new();
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        loop {
+            let order = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => first_unit::<K>(&l.prefix).cmp(first_unit::<K>(&r.prefix)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return merged,
+            };
+            let node = match order {
+                Ordering::Less => {
+                    let node = left.next().unwrap();
+                    if !self.left {
+                        continue;
+                    }
+                    node
+                }
+                Ordering::Greater => {
+                    let node = right.next().unwrap();
+                    match self.right {
+                        Some((take, _)) => take(node),
+                        None => continue,
+                    }
+                }
+                Ordering::Equal => {
+                    let node = self.nodes(left.next().unwrap(), right.next().unwrap());
+                    match compact(node) {
+                        Some(node) => node,
+                        None => continue,
+                    }
+                }
+            };
+            merged.insert(node);
+        }
+    }
+}
+
+fn single<A: TrieAtom, V>(node: TrieNode<A, V>) -> Children<A, V> {
+    let mut children = Children::new();
+    children.insert(node);
+    children
+}
+
+/// drops a valueless node without children, and folds a valueless node
+/// into its only child
+fn compact<A: TrieAtom, V>(mut node: TrieNode<A, V>) -> Option<TrieNode<A, V>> {
+    if node.value.is_some() {
+        return Some(node);
+    }
+    match node.children.len() {
+        0 => None,
+        1 => {
+            node.take_only_child();
+            Some(node)
+        }
+        _ => Some(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::words;
+    use std::collections::BTreeMap;
+
+    const LEFT: [&str; 12] = [
+        "", "a", "and", "anthem", "band", "bandana", "café", "car", "cart", "dog", "日本", "日曜",
+    ];
+    // cafè and 日本語 part from the left keys inside of a character's bytes
+    const RIGHT: [&str; 11] = [
+        "an",
+        "and",
+        "ant",
+        "bandana",
+        "banner",
+        "cafè",
+        "cart",
+        "cartoon",
+        "日本語",
+        "日付",
+        "zoo",
+    ];
+
+    /// checks contents, and that the shape is what inserting them gives
+    fn check(trie: Trie<usize>, expected: BTreeMap<String, usize>) {
+        assert_eq!(trie.validate(), Ok(()));
+        let mut canonical: Trie<usize> = Trie::new();
+        for (key, value) in expected.iter() {
+            canonical.set(key, *value);
+        }
+        assert_eq!(trie.size(), canonical.size());
+        assert_eq!(trie.len(), expected.len());
+        let found: BTreeMap<String, usize> = trie.into_iter().collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn set_operations() {
+        // values apart from the left ones
+        let other = || -> Trie<usize> {
+            words(&RIGHT)
+                .into_iter()
+                .map(|(key, value)| (key, value + 100))
+                .collect()
+        };
+        let trie = || words(&LEFT);
+        let left: BTreeMap<String, usize> = trie().into_iter().collect();
+        let right: BTreeMap<String, usize> = other().into_iter().collect();
+
+        let mut union = right.clone();
+        for (key, value) in left.iter() {
+            *union.entry(key.clone()).or_insert(0) += value;
+        }
+        check(trie().union_with(other(), |a, b| a + b), union);
+
+        let intersection: BTreeMap<String, usize> = left
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value * 1000 + right.get(key)?)))
+            .collect();
+        check(
+            trie().intersection(other(), |a, b| a * 1000 + b),
+            intersection,
+        );
+
+        let difference: BTreeMap<String, usize> = left
+            .iter()
+            .filter(|(key, _)| !right.contains_key(*key))
+            .map(|(key, value)| (key.clone(), *value))
+            .collect();
+        check(trie().difference(other()), difference.clone());
+
+        let mut symmetric = difference;
+        symmetric.extend(
+            right
+                .iter()
+                .filter(|(key, _)| !left.contains_key(*key))
+                .map(|(key, value)| (key.clone(), *value)),
+        );
+        check(trie().symmetric_difference(other()), symmetric);
+    }
+
+    #[test]
+    fn with_empty_and_self() {
+        let map: BTreeMap<String, usize> = words(&LEFT).into_iter().collect();
+        let empty: Trie<usize> = Trie::new();
+        check(words(&LEFT).union_with(Trie::new(), |a, _| a), map.clone());
+        check(empty.union_with(words(&LEFT), |a, _| a), map.clone());
+        check(
+            words(&LEFT).intersection(words(&LEFT), |a, b| a + b),
+            map.iter()
+                .map(|(key, value)| (key.clone(), value * 2))
+                .collect(),
+        );
+        check(words(&LEFT).difference(words(&LEFT)), BTreeMap::new());
+        // the other side's values may be of any type when only keys matter
+        let mut flags: Trie<bool> = Trie::new();
+        flags.set("and", true);
+        flags.set("an", true);
+        let mut expected = map.clone();
+        expected.remove("and");
+        check(words(&LEFT).difference(flags), expected);
+    }
+}
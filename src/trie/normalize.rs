@@ -0,0 +1,325 @@
+//! string keys looked up by a normalized form
+//!
+//! keys are stored under their normalized form, with the spelling they were
+//! first set with kept next to the value. any spelling normalizing the same
+//! way finds the key, and iteration reports the stored spellings.
+
+use std::iter::FusedIterator;
+
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::UnicodeNormalization;
+
+use super::{Entry, KeyNotFoundError, Trie, TrieKey, Values};
+
+/// how keys are normalized before they are stored or looked up
+///
+/// keys are trimmed first, then brought to their unicode form, case mapped,
+/// and brought to their unicode form again, as nfkc casefold does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// how case differences are matched
+    pub case: Case,
+    /// drop leading and trailing whitespace
+    pub trim: bool,
+    /// the unicode form keys are brought to
+    pub form: UnicodeForm,
+}
+
+/// how case differences in keys are matched
+///
+/// some variants depend on crate features, so matches need a wildcard arm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Case {
+    /// case is kept, keys differing in case differ
+    #[default]
+    AsIs,
+    /// keys are lowercased, which matches most but not all case differences,
+    /// e.g. "straße" does not match "STRASSE"
+    Lower,
+    /// full unicode case folding, so e.g. "straße" matches "STRASSE"
+    #[cfg(feature = "case-folding")]
+    Fold,
+}
+
+/// the unicode normalization form keys are brought to
+///
+/// forms other than `AsIs` need the `unicode-normalization` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnicodeForm {
+    /// code points are compared as they are
+    #[default]
+    AsIs,
+    /// canonical composition, so precomposed and combined characters match
+    #[cfg(feature = "unicode-normalization")]
+    Nfc,
+    /// compatibility composition, so e.g. ligatures and full width forms
+    /// match their plain characters too
+    #[cfg(feature = "unicode-normalization")]
+    Nfkc,
+}
+
+impl Normalization {
+    /// normalizes a key
+    pub fn apply(&self, key: &str) -> String {
+        match self.trim {
+            true => self.map(key.trim()),
+            false => self.map(key),
+        }
+    }
+
+    /// normalizes a key, except for trimming
+    fn map(&self, key: &str) -> String {
+        let key = self.form.apply(key.to_string());
+        match self.case {
+            Case::AsIs => key,
+            // case mapping can leave keys out of form, e.g. by decomposing
+            Case::Lower => self.form.apply(key.to_lowercase()),
+            #[cfg(feature = "case-folding")]
+            Case::Fold => self.form.apply(caseless::default_case_fold_str(&key)),
+        }
+    }
+}
+
+impl UnicodeForm {
+    fn apply(self, key: String) -> String {
+        match self {
+            UnicodeForm::AsIs => key,
+            #[cfg(feature = "unicode-normalization")]
+            UnicodeForm::Nfc => key.nfc().collect(),
+            #[cfg(feature = "unicode-normalization")]
+            UnicodeForm::Nfkc => key.nfkc().collect(),
+        }
+    }
+}
+
+/// a trie over string keys that treats spellings normalizing alike as one key
+#[derive(Debug)]
+pub struct NormalizedTrie<V> {
+    normalization: Normalization,
+    /// normalized keys, with the spelling first set and the value
+    trie: Trie<(String, V)>,
+}
+
+impl<V> NormalizedTrie<V> {
+    /// constructs an empty trie normalizing keys as given
+    pub fn new(normalization: Normalization) -> Self {
+        NormalizedTrie {
+            normalization,
+            trie: Trie::new(),
+        }
+    }
+
+    /// the normalization keys go through
+    #[inline]
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// gets the value of a key
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let (_, value) = self.trie.get(&self.normalization.apply(key))?;
+        Some(value)
+    }
+
+    /// gets the value of a key as mutable
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let (_, value) = self.trie.get_mut(&self.normalization.apply(key))?;
+        Some(value)
+    }
+
+    /// gets the spelling a key is stored with
+    pub fn spelling(&self, key: &str) -> Option<&str> {
+        let (spelling, _) = self.trie.get(&self.normalization.apply(key))?;
+        Some(spelling)
+    }
+
+    /// checks if a key exists
+    pub fn has(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// sets a key to a value
+    /// returns the key evicted if there was already a key.
+    ///
+    /// a key that exists keeps the spelling it was first set with.
+    pub fn set(&mut self, key: &str, val: V) -> Option<V> {
+        match self.trie.entry(&self.normalization.apply(key)) {
+            Entry::Occupied(mut entry) => Some(std::mem::replace(&mut entry.get_mut().1, val)),
+            Entry::Vacant(entry) => {
+                entry.insert((key.to_string(), val));
+                None
+            }
+        }
+    }
+
+    /// removes a key, returns its value
+    pub fn remove(&mut self, key: &str) -> Result<V, KeyNotFoundError> {
+        match self.trie.remove(&self.normalization.apply(key)) {
+            Ok((_, value)) => Ok(value),
+            Err(_) => Err(KeyNotFoundError {
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    /// iterates over stored spellings and values, in order of normalized keys
+    pub fn iter(&self) -> Spellings<'_, V> {
+        Spellings(self.trie.values())
+    }
+
+    /// iterates over stored spellings and values of the keys whose normalized
+    /// form starts with the normalized prefix, in order of normalized keys
+    ///
+    /// trailing whitespace of the prefix is kept, it is part of what is searched for.
+    pub fn iter_prefix(&self, prefix: &str) -> Spellings<'_, V> {
+        let prefix = match self.normalization.trim {
+            true => self.normalization.map(prefix.trim_start()),
+            false => self.normalization.map(prefix),
+        };
+        let stack = match self.trie.root.cover(String::atoms(&prefix)) {
+            Some((_, node)) => vec![node],
+            None => Vec::new(),
+        };
+        Spellings(Values { stack })
+    }
+}
+
+/// iteration over the spellings and values of a normalized trie
+pub struct Spellings<'a, V>(Values<'a, String, (String, V)>);
+
+impl<'a, V> Iterator for Spellings<'a, V> {
+    type Item = (&'a str, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (spelling, value) = self.0.next()?;
+        Some((spelling, value))
+    }
+}
+
+impl<V> FusedIterator for Spellings<'_, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_and_whitespace() {
+        let mut table = NormalizedTrie::new(Normalization {
+            case: Case::Lower,
+            trim: true,
+            ..Normalization::default()
+        });
+        assert_eq!(table.set("Foo", 1), None);
+        assert_eq!(table.set("  BAR baz ", 2), None);
+        assert_eq!(table.set("Ärger", 3), None);
+        assert_eq!(table.get("foo"), Some(&1));
+        assert_eq!(table.get("FOO "), Some(&1));
+        assert_eq!(table.get("bar BAZ"), Some(&2));
+        assert_eq!(table.get("bar  baz"), None);
+        assert_eq!(table.get("ärger"), Some(&3));
+        // the first spelling is kept
+        assert_eq!(table.set("fOO", 4), Some(1));
+        assert_eq!(table.spelling("foo"), Some("Foo"));
+        assert_eq!(table.len(), 3);
+        let found: Vec<(&str, &i32)> = table.iter().collect();
+        assert_eq!(found, vec![("  BAR baz ", &2), ("Foo", &4), ("Ärger", &3)]);
+        let found: Vec<&str> = table.iter_prefix("F").map(|(key, _)| key).collect();
+        assert_eq!(found, vec!["Foo"]);
+        assert_eq!(table.iter_prefix("x").count(), 0);
+        *table.get_mut("ÄRGER").unwrap() += 10;
+        assert_eq!(table.remove("ärger"), Ok(13));
+        assert_eq!(
+            table.remove("ärger"),
+            Err(KeyNotFoundError {
+                key: "ärger".to_string()
+            })
+        );
+        assert!(!table.has("Ärger"));
+
+        // lowercasing does not fold every case difference
+        table.set("straße", 5);
+        assert_eq!(table.get("STRASSE"), None);
+        assert_eq!(table.get("Straße"), Some(&5));
+
+        // without a policy, keys are compared as they are
+        let mut table = NormalizedTrie::new(Normalization::default());
+        table.set("Foo", 1);
+        assert_eq!(table.get("foo"), None);
+        assert_eq!(table.get("Foo"), Some(&1));
+    }
+
+    #[test]
+    fn prefix_whitespace() {
+        let mut table = NormalizedTrie::new(Normalization {
+            case: Case::Lower,
+            trim: true,
+            ..Normalization::default()
+        });
+        table.set("New York", 1);
+        table.set("Newark", 2);
+        let found: Vec<&str> = table.iter_prefix("new ").map(|(key, _)| key).collect();
+        assert_eq!(found, vec!["New York"]);
+        let found: Vec<&str> = table.iter_prefix("  new").map(|(key, _)| key).collect();
+        assert_eq!(found, vec!["New York", "Newark"]);
+    }
+
+    #[cfg(feature = "case-folding")]
+    #[test]
+    fn case_folding() {
+        let mut table = NormalizedTrie::new(Normalization {
+            case: Case::Fold,
+            ..Normalization::default()
+        });
+        table.set("straße", 1);
+        table.set("ΣΊΣΥΦΟΣ", 2);
+        assert_eq!(table.get("STRASSE"), Some(&1));
+        assert_eq!(table.get("Strasse"), Some(&1));
+        assert_eq!(table.get("σίσυφος"), Some(&2));
+        assert_eq!(table.spelling("strasse"), Some("straße"));
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn unicode_forms() {
+        let mut table = NormalizedTrie::new(Normalization {
+            form: UnicodeForm::Nfc,
+            ..Normalization::default()
+        });
+        table.set("cafe\u{301}", 1);
+        assert_eq!(table.get("café"), Some(&1));
+        assert_eq!(table.spelling("café"), Some("cafe\u{301}"));
+        table.set("ﬁle", 2);
+        assert_eq!(table.get("file"), None);
+
+        let mut table = NormalizedTrie::new(Normalization {
+            case: Case::Lower,
+            trim: false,
+            form: UnicodeForm::Nfkc,
+        });
+        table.set("ﬁle", 2);
+        table.set("ＡＢＣ", 3);
+        // compatibility forms are mapped before case, "ℌ" becomes "H" and then "h"
+        table.set("ℌello", 4);
+        assert_eq!(table.get("FILE"), Some(&2));
+        assert_eq!(table.get("abc"), Some(&3));
+        assert_eq!(table.get(" abc"), None);
+        assert_eq!(table.get("hello"), Some(&4));
+        assert_eq!(table.get("Hello"), Some(&4));
+        let found: Vec<&str> = table.iter().map(|(key, _)| key).collect();
+        assert_eq!(found, vec!["ＡＢＣ", "ﬁle", "ℌello"]);
+    }
+}
@@ -0,0 +1,402 @@
+//! persistent, structurally shared prefix tree
+//!
+//! every update copies the nodes on the path of the key and shares the rest
+//! with the version it was made from, so old versions stay valid and
+//! taking a snapshot is a reference count bump.
+
+use std::iter::FusedIterator;
+use std::sync::Arc;
+
+use super::{common_prefix_len, first_unit, unit_floor, TrieKey};
+
+/// compressed prefix tree whose updates return new versions
+///
+/// cloning is O(1), clones share all of their nodes.
+/// versions can be handed to other threads while another keeps updating.
+#[derive(Debug)]
+pub struct PersistentTrie<K: TrieKey, V> {
+    /// tree root
+    /// this will always be a node with the empty prefix.
+    root: Arc<PersistentNode<K::Atom, V>>,
+    /// number of keys
+    len: usize,
+}
+
+#[derive(Debug)]
+struct PersistentNode<A, V> {
+    prefix: Vec<A>,
+    /// sorted by first unit, which is unique among siblings
+    children: Vec<Arc<PersistentNode<A, V>>>,
+    value: Option<Arc<V>>,
+}
+
+impl<A, V> Clone for PersistentNode<A, V>
+where
+    A: Clone,
+{
+    /// copies the node itself, children and value stay shared
+    fn clone(&self) -> Self {
+        PersistentNode {
+            prefix: self.prefix.clone(),
+            children: self.children.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<K: TrieKey, V> Clone for PersistentTrie<K, V> {
+    fn clone(&self) -> Self {
+        PersistentTrie {
+            root: Arc::clone(&self.root),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: TrieKey, V> PersistentTrie<K, V> {
+    /// constructs an empty prefix tree
+    pub fn new() -> Self {
+        PersistentTrie {
+            root: Arc::new(PersistentNode {
+                prefix: Vec::new(),
+                children: Vec::new(),
+                value: None,
+            }),
+            len: 0,
+        }
+    }
+
+    /// gets the value of a key
+    pub fn get(&self, key: &K::Slice) -> Option<&V> {
+        let mut node = &*self.root;
+        let mut rest = K::atoms(key);
+        loop {
+            if rest.is_empty() {
+                return node.value.as_deref();
+            }
+            node = node.leaf(rest)?;
+            rest = &rest[node.prefix.len()..];
+        }
+    }
+
+    /// checks if a key exists
+    #[inline]
+    pub fn has(&self, key: &K::Slice) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// makes a new version with a key set to a value
+    ///
+    /// this version is left as it is.
+    pub fn set(&self, key: &K::Slice, val: V) -> Self {
+        let (root, added) = self.root.insert::<K>(K::atoms(key), Arc::new(val));
+        PersistentTrie {
+            root: Arc::new(root),
+            len: self.len + usize::from(added),
+        }
+    }
+
+    /// makes a new version without a key
+    ///
+    /// this version is left as it is. if the key does not exist,
+    /// the new version shares everything with this one.
+    pub fn remove(&self, key: &K::Slice) -> Self {
+        match self.root.remove(K::atoms(key)) {
+            None => self.clone(),
+            // the root is never compacted, it keeps the empty prefix
+            Some(root) => PersistentTrie {
+                root: Arc::new(root),
+                len: self.len - 1,
+            },
+        }
+    }
+
+    /// checks if two versions are the same one, without comparing contents
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+
+    /// iterates over key/value pairs in lexicographic key order
+    pub fn iter(&self) -> PersistentIteration<'_, K, V> {
+        PersistentIteration {
+            stack: vec![(0, &*self.root)],
+            key: Vec::new(),
+        }
+    }
+}
+
+impl<K: TrieKey, V> Default for PersistentTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Ord + Clone, V> PersistentNode<A, V> {
+    fn leaf(&self, key: &[A]) -> Option<&Self> {
+        let idx = self.child_index(key).ok()?;
+        let node = &*self.children[idx];
+        key.starts_with(&node.prefix).then_some(node)
+    }
+
+    /// finds the child key leads into, whose prefix and key agree up to
+    /// the shorter of the two, or where a child starting with key would go
+    ///
+    /// key is at least the first unit of a key, which one child at most starts with.
+    fn child_index(&self, key: &[A]) -> Result<usize, usize> {
+        self.children.binary_search_by(|child| {
+            let len = child.prefix.len().min(key.len());
+            child.prefix[..len].cmp(&key[..len])
+        })
+    }
+
+    /// copies this node with key set to value
+    ///
+    /// key includes our prefix. returns the copy, and if the key is new.
+    fn insert<K: TrieKey<Atom = A>>(&self, key: &[A], value: Arc<V>) -> (Self, bool) {
+        let mut copy = self.clone();
+        if key == self.prefix {
+            let added = copy.value.replace(value).is_none();
+            return (copy, added);
+        }
+        let rest = &key[self.prefix.len()..];
+        match self.child_index(first_unit::<K>(rest)) {
+            Err(idx) => {
+                copy.children
+                    .insert(idx, Arc::new(PersistentNode::leaf_of(rest, value)));
+                (copy, true)
+            }
+            // still longer than leaf, and leaf exists
+            Ok(idx) if rest.starts_with(&self.children[idx].prefix) => {
+                let (child, added) = self.children[idx].insert::<K>(rest, value);
+                copy.children[idx] = Arc::new(child);
+                (copy, added)
+            }
+            // shares the first unit of a leaf's prefix, split the leaf where
+            // the last whole unit of the common part ends
+            Ok(idx) => {
+                let child = &self.children[idx];
+                let common = unit_floor::<K>(rest, common_prefix_len(&child.prefix, rest));
+                let mut moved = (**child).clone();
+                moved.prefix.drain(..common);
+                let mut split = PersistentNode {
+                    prefix: rest[..common].to_vec(),
+                    children: vec![Arc::new(moved)],
+                    value: None,
+                };
+                if common == rest.len() {
+                    split.value = Some(value);
+                } else {
+                    let leaf = PersistentNode::leaf_of(&rest[common..], value);
+                    let at = split.child_index(&leaf.prefix).unwrap_err();
+                    split.children.insert(at, Arc::new(leaf));
+                }
+                copy.children[idx] = Arc::new(split);
+                (copy, true)
+            }
+        }
+    }
+
+    fn leaf_of(prefix: &[A], value: Arc<V>) -> Self {
+        PersistentNode {
+            prefix: prefix.to_vec(),
+            children: Vec::new(),
+            value: Some(value),
+        }
+    }
+
+    /// copies this node without key
+    ///
+    /// key includes our prefix. returns None if the key does not exist.
+    /// the copy is left for the parent to compact.
+    fn remove(&self, key: &[A]) -> Option<Self> {
+        let mut copy = self.clone();
+        if key == self.prefix {
+            copy.value.take()?;
+            return Some(copy);
+        }
+        let rest = &key[self.prefix.len()..];
+        let idx = self.child_index(rest).ok()?;
+        let child = &self.children[idx];
+        if !rest.starts_with(&child.prefix) {
+            return None;
+        }
+        match child.remove(rest)?.compact() {
+            None => {
+                copy.children.remove(idx);
+            }
+            Some(child) => copy.children[idx] = Arc::new(child),
+        }
+        Some(copy)
+    }
+
+    /// restores compression after a removal below or at this node
+    ///
+    /// valueless nodes vanish if they have no children,
+    /// and take their child in if they have one.
+    fn compact(mut self) -> Option<Self> {
+        if self.value.is_some() || self.children.len() > 1 {
+            return Some(self);
+        }
+        let child = Arc::unwrap_or_clone(self.children.pop()?);
+        self.prefix.extend(child.prefix);
+        self.children = child.children;
+        self.value = child.value;
+        Some(self)
+    }
+}
+
+/// key ordered iteration over a persistent trie
+pub struct PersistentIteration<'a, K: TrieKey, V> {
+    /// nodes left to visit, with the length of the key leading up to them
+    stack: Vec<(usize, &'a PersistentNode<K::Atom, V>)>,
+    /// key of the last node visited
+    key: Vec<K::Atom>,
+}
+
+impl<'a, K: TrieKey, V> Iterator for PersistentIteration<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.prefix);
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.iter().rev().map(|child| (depth, &**child)));
+            if let Some(value) = node.value.as_deref() {
+                return Some((K::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<K: TrieKey, V> FusedIterator for PersistentIteration<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::pairs;
+
+    #[test]
+    fn versions_stay_valid() {
+        let empty: PersistentTrie<String, i32> = PersistentTrie::new();
+        let v1 = empty.set("abc", 1).set("abd", 2).set("b", 3);
+        let v2 = v1.set("ab", 4).set("abc", 10);
+        let v3 = v2.remove("abd").remove("b");
+        assert!(empty.is_empty());
+        assert_eq!(
+            pairs(v1.iter()),
+            vec![("abc".into(), 1), ("abd".into(), 2), ("b".into(), 3)]
+        );
+        assert_eq!(
+            pairs(v2.iter()),
+            vec![
+                ("ab".into(), 4),
+                ("abc".into(), 10),
+                ("abd".into(), 2),
+                ("b".into(), 3)
+            ]
+        );
+        assert_eq!(pairs(v3.iter()), vec![("ab".into(), 4), ("abc".into(), 10)]);
+        assert_eq!((v1.len(), v2.len(), v3.len()), (3, 4, 2));
+        assert_eq!(v1.get("ab"), None);
+        assert_eq!(v3.get("abc"), Some(&10));
+        assert_eq!(v3.get("abd"), None);
+        // removing what is not there shares everything
+        assert!(v3.remove("zzz").ptr_eq(&v3));
+        let gone = v3.remove("ab").remove("abc");
+        assert!(gone.is_empty());
+        assert_eq!(gone.root.children.len(), 0);
+    }
+
+    #[test]
+    fn shares_untouched_subtrees() {
+        let mut trie: PersistentTrie<String, usize> = PersistentTrie::new();
+        for (i, key) in ["apple", "apricot", "banana", "blueberry", "cherry"]
+            .iter()
+            .enumerate()
+        {
+            trie = trie.set(*key, i);
+        }
+        let snapshot = trie.clone();
+        assert!(snapshot.ptr_eq(&trie));
+        let updated = trie.set("banana", 100).remove("cherry");
+        // the a branch was not on any updated path
+        assert!(Arc::ptr_eq(
+            &trie.root.children[0],
+            &updated.root.children[0]
+        ));
+        assert!(!Arc::ptr_eq(
+            &trie.root.children[1],
+            &updated.root.children[1]
+        ));
+        assert_eq!(snapshot.get("banana"), Some(&2));
+        assert_eq!(snapshot.get("cherry"), Some(&4));
+        assert_eq!(updated.get("banana"), Some(&100));
+        assert_eq!(updated.get("cherry"), None);
+        assert_eq!(updated.root.children.len(), 2);
+    }
+
+    #[test]
+    fn compacts_on_removal() {
+        let trie: PersistentTrie<String, u8> =
+            PersistentTrie::new().set("a", 1).set("ab", 2).set("abc", 3);
+        // removing a middle value merges the chain below it
+        let trie = trie.remove("ab");
+        assert_eq!(trie.get("abc"), Some(&3));
+        assert_eq!(trie.get("a"), Some(&1));
+        let a = &trie.root.children[0];
+        assert_eq!(a.children.len(), 1);
+        assert_eq!(a.children[0].prefix, b"bc".to_vec());
+        let trie = trie.remove("a");
+        assert_eq!(trie.root.children[0].prefix, b"abc".to_vec());
+        assert_eq!(trie.len(), 1);
+    }
+
+    #[test]
+    fn splits_between_characters() {
+        let mut trie: PersistentTrie<String, usize> = PersistentTrie::new();
+        for (i, key) in ["café", "cafè", "caf", "日本", "日曜"].iter().enumerate() {
+            trie = trie.set(*key, i);
+        }
+        // siblings part on their first character, even where they share a byte
+        let caf = &trie.root.children[0];
+        assert_eq!(caf.prefix, b"caf".to_vec());
+        let prefixes: Vec<&[u8]> = caf.children.iter().map(|c| &c.prefix[..]).collect();
+        assert_eq!(prefixes, vec!["è".as_bytes(), "é".as_bytes()]);
+        assert_eq!(trie.get("cafè"), Some(&1));
+        assert_eq!(trie.get("日本"), Some(&3));
+        let trie = trie.remove("café").remove("日本");
+        let keys: Vec<String> = trie.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["caf", "cafè", "日曜"]);
+        assert_eq!(trie.root.children[1].prefix, "日曜".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn readers_on_other_threads() {
+        let mut trie: PersistentTrie<String, usize> = PersistentTrie::new();
+        let mut readers = Vec::new();
+        for i in 0..8 {
+            trie = trie.set(&i.to_string(), i);
+            let version = trie.clone();
+            readers.push(std::thread::spawn(move || {
+                (0..=i).all(|j| version.get(&j.to_string()) == Some(&j)) && version.len() == i + 1
+            }));
+        }
+        assert!(readers.into_iter().all(|reader| reader.join().unwrap()));
+    }
+}
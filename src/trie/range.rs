@@ -0,0 +1,185 @@
+//! ordered access by key bounds
+//!
+//! a subtree only holds keys starting with the key of its root, and none
+//! smaller than it, so a subtree is skipped whole when its key is below the
+//! start bound without leading up to it, and iteration stops at the first
+//! key past the end bound.
+
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+
+use super::{Trie, TrieKey, TrieNode};
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// iterates over key/value pairs with keys in range, in lexicographic key order
+    ///
+    /// works like `BTreeMap::range`, e.g. `trie.range("b".."d")`.
+    pub fn range<'r, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        R: RangeBounds<&'r K::Slice>,
+        K::Slice: 'r,
+    {
+        Range {
+            start: range.start_bound().map(|key| K::atoms(*key).to_vec()),
+            end: range.end_bound().map(|key| K::atoms(*key).to_vec()),
+            stack: vec![(0, &self.root)],
+            key: Vec::new(),
+        }
+    }
+
+    /// gets the lexicographically first key and its value
+    pub fn first_key_value(&self) -> Option<(K, &V)> {
+        self.iter().next()
+    }
+
+    /// gets the lexicographically last key and its value
+    pub fn last_key_value(&self) -> Option<(K, &V)> {
+        // the last key is the deepest one down the last children
+        let mut key = Vec::new();
+        let mut node = &self.root;
+        let mut last = None;
+        loop {
+            key.extend_from_slice(&node.prefix);
+            if node.value.is_some() {
+                last = Some((key.len(), node));
+            }
+            match node.children.iter().next_back() {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        let (depth, node) = last?;
+        Some((K::from_atoms(&key[..depth]), node.value.as_ref()?))
+    }
+}
+
+/// key ordered iteration over the keys of a trie within bounds
+pub struct Range<'a, K: TrieKey, V> {
+    start: Bound<Vec<K::Atom>>,
+    end: Bound<Vec<K::Atom>>,
+    /// nodes left to visit, with the length of the key leading up to them
+    stack: Vec<(usize, &'a TrieNode<K::Atom, V>)>,
+    /// key of the last node visited
+    key: Vec<K::Atom>,
+}
+
+impl<K: TrieKey, V> Range<'_, K, V> {
+    /// checks if keys starting with the current key can be at or past the start
+    fn reaches_start(&self) -> bool {
+        match &self.start {
+            Bound::Unbounded => true,
+            Bound::Included(start) | Bound::Excluded(start) => {
+                self.key >= *start || start.starts_with(&self.key)
+            }
+        }
+    }
+
+    /// checks if the current key is at or past the start
+    fn after_start(&self) -> bool {
+        match &self.start {
+            Bound::Unbounded => true,
+            Bound::Included(start) => self.key >= *start,
+            Bound::Excluded(start) => self.key > *start,
+        }
+    }
+
+    /// checks if the current key is past the end
+    fn past_end(&self) -> bool {
+        match &self.end {
+            Bound::Unbounded => false,
+            Bound::Included(end) => self.key > *end,
+            Bound::Excluded(end) => self.key >= *end,
+        }
+    }
+}
+
+impl<'a, K: TrieKey, V> Iterator for Range<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, node)) = self.stack.pop() {
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.prefix);
+            if !self.reaches_start() {
+                continue;
+            }
+            // every key still to come is at least this one
+            if self.past_end() {
+                self.stack.clear();
+                return None;
+            }
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.iter().rev().map(|child| (depth, child)));
+            if !self.after_start() {
+                continue;
+            }
+            if let Some(value) = node.value.as_ref() {
+                return Some((K::from_atoms(&self.key), value));
+            }
+        }
+        None
+    }
+}
+
+impl<K: TrieKey, V> FusedIterator for Range<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::{pairs, words};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn matches_btree_ranges() {
+        let trie = words(&[
+            "", "a", "ab", "abc", "abd", "b", "ba", "banana", "band", "bandana", "c", "car",
+            "card", "care", "zebra",
+        ]);
+        let map: BTreeMap<String, usize> = pairs(trie.iter()).into_iter().collect();
+        let bounds = [
+            "", "a", "aa", "abc", "abz", "b", "ban", "bandanas", "ca", "card", "zz",
+        ];
+        let expect = |range: (Bound<&str>, Bound<&str>)| -> Vec<(String, usize)> {
+            map.range::<str, _>(range)
+                .map(|(key, value)| (key.clone(), *value))
+                .collect()
+        };
+        let found = |range: (Bound<&str>, Bound<&str>)| pairs(trie.range(range));
+        for low in bounds {
+            for high in bounds.iter().filter(|high| **high >= low) {
+                for range in [
+                    (Bound::Included(low), Bound::Included(*high)),
+                    (Bound::Included(low), Bound::Excluded(*high)),
+                    (Bound::Excluded(low), Bound::Included(*high)),
+                    (Bound::Included(low), Bound::Unbounded),
+                    (Bound::Excluded(low), Bound::Unbounded),
+                    (Bound::Unbounded, Bound::Excluded(*high)),
+                ] {
+                    assert_eq!(found(range), expect(range), "{range:?}");
+                }
+            }
+        }
+        let keys: Vec<String> = trie.range("b".."c").map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["b", "ba", "banana", "band", "bandana"]);
+        assert_eq!(trie.range(.."a").count(), 1);
+        assert_eq!(trie.range::<std::ops::RangeFull>(..).count(), map.len());
+        assert_eq!(trie.range("car"..="car").count(), 1);
+    }
+
+    #[test]
+    fn first_and_last() {
+        let mut trie: Trie<usize> = Trie::new();
+        assert_eq!(trie.first_key_value(), None);
+        assert_eq!(trie.last_key_value(), None);
+        trie.set("band", 1);
+        trie.set("banana", 2);
+        trie.set("ban", 3);
+        assert_eq!(trie.first_key_value(), Some(("ban".to_string(), &3)));
+        assert_eq!(trie.last_key_value(), Some(("band".to_string(), &1)));
+        trie.set("", 4);
+        trie.set("bandana", 5);
+        assert_eq!(trie.first_key_value(), Some(("".to_string(), &4)));
+        assert_eq!(trie.last_key_value(), Some(("bandana".to_string(), &5)));
+    }
+}
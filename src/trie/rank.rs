@@ -0,0 +1,142 @@
+//! ranked completions under a prefix
+//!
+//! scores are computed by the caller from values, so nodes carry no cached
+//! bounds and the subtree under the prefix is walked in full. only the best
+//! k candidates are kept on a heap, and keys are only built for those.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use super::{Trie, TrieKey};
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// gets the k highest scoring keys starting with prefix, best first
+    ///
+    /// ties go to the lexicographically smaller key.
+    pub fn top_k_completions<S, F>(&self, prefix: &K::Slice, k: usize, mut score: F) -> Vec<(K, &V)>
+    where
+        S: Ord,
+        F: FnMut(&V) -> S,
+    {
+        if k == 0 {
+            return Vec::new();
+        }
+        let prefix = K::atoms(prefix);
+        let Some((depth, node)) = self.root.cover(prefix) else {
+            return Vec::new();
+        };
+        // worst candidate on top, so it can be dropped when a better one comes
+        let mut best: BinaryHeap<Reverse<Candidate<S, K, &V>>> = BinaryHeap::with_capacity(k + 1);
+        let mut key = prefix[..depth].to_vec();
+        let mut stack = vec![(depth, node)];
+        let mut order = 0;
+        while let Some((depth, node)) = stack.pop() {
+            key.truncate(depth);
+            key.extend_from_slice(&node.prefix);
+            let depth = key.len();
+            stack.extend(node.children.iter().rev().map(|child| (depth, child)));
+            let Some(value) = node.value.as_ref() else {
+                continue;
+            };
+            let score = score(value);
+            order += 1;
+            if best.len() == k && best.peek().is_some_and(|worst| !worst.0.beaten_by(&score)) {
+                continue;
+            }
+            best.push(Reverse(Candidate {
+                score,
+                order,
+                key: K::from_atoms(&key),
+                value,
+            }));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(candidate)| (candidate.key, candidate.value))
+            .collect()
+    }
+}
+
+/// a scored key, ordered from worst to best
+struct Candidate<S, K, V> {
+    score: S,
+    /// position in key order, earlier is better on equal scores
+    order: usize,
+    key: K,
+    value: V,
+}
+
+impl<S: Ord, K, V> Candidate<S, K, V> {
+    /// checks if a later key with this score would rank above us
+    fn beaten_by(&self, score: &S) -> bool {
+        *score > self.score
+    }
+}
+
+impl<S: Ord, K, V> Ord for Candidate<S, K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .cmp(&other.score)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl<S: Ord, K, V> PartialOrd for Candidate<S, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: Ord, K, V> PartialEq for Candidate<S, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S: Ord, K, V> Eq for Candidate<S, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_completions() {
+        let mut trie: Trie<u32> = Trie::new();
+        for (word, hits) in [
+            ("car", 50),
+            ("card", 10),
+            ("care", 80),
+            ("career", 80),
+            ("cargo", 30),
+            ("cart", 5),
+            ("cat", 99),
+            ("dog", 1000),
+        ] {
+            trie.set(word, hits);
+        }
+        let top = |prefix, k| {
+            trie.top_k_completions(prefix, k, |hits| *hits)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        // ties keep key order
+        assert_eq!(top("car", 3), vec!["care", "career", "car"]);
+        assert_eq!(top("ca", 2), vec!["cat", "care"]);
+        assert_eq!(top("carg", 5), vec!["cargo"]);
+        assert_eq!(top("", 1), vec!["dog"]);
+        assert_eq!(top("car", 0), Vec::<String>::new());
+        assert_eq!(top("cz", 3), Vec::<String>::new());
+        assert_eq!(top("c", 100).len(), 7);
+        // lowest first by flipping the score
+        let bottom: Vec<String> = trie
+            .top_k_completions("car", 2, |hits| Reverse(*hits))
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(bottom, vec!["cart", "card"]);
+    }
+}
@@ -0,0 +1,116 @@
+//! shape and memory statistics
+
+use std::mem::size_of;
+
+use super::{Trie, TrieKey};
+
+/// report on the shape and memory use of a trie
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrieStats {
+    /// number of keys
+    pub keys: usize,
+    /// number of nodes, including the root
+    pub nodes: usize,
+    /// most nodes below the root a key sits at
+    pub max_depth: usize,
+    /// average number of nodes below the root keys sit at
+    pub avg_depth: f64,
+    /// number of nodes by their number of children
+    pub branching: Vec<usize>,
+    /// bytes of atoms stored in node prefixes
+    pub prefix_bytes: usize,
+    /// bytes of atoms the keys take when stored one by one
+    pub key_bytes: usize,
+    /// estimated bytes allocated by the tree, not counting what values allocate
+    pub heap_bytes: usize,
+}
+
+impl TrieStats {
+    /// gets the share of key bytes that is stored, lower is better
+    pub fn compression_ratio(&self) -> f64 {
+        if self.key_bytes == 0 {
+            return 1.0;
+        }
+        self.prefix_bytes as f64 / self.key_bytes as f64
+    }
+}
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// walks the tree to report on its shape and memory use
+    pub fn stats(&self) -> TrieStats {
+        let atom = size_of::<K::Atom>();
+        let mut stats = TrieStats {
+            keys: 0,
+            nodes: 0,
+            max_depth: 0,
+            avg_depth: 0.0,
+            branching: Vec::new(),
+            prefix_bytes: 0,
+            key_bytes: 0,
+            heap_bytes: 0,
+        };
+        let mut depths = 0;
+        // nodes with their depth and the length of their key
+        let mut stack = vec![(0, self.root.prefix.len(), &self.root)];
+        while let Some((depth, key_len, node)) = stack.pop() {
+            stats.nodes += 1;
+            let children = node.children.len();
+            if stats.branching.len() <= children {
+                stats.branching.resize(children + 1, 0);
+            }
+            stats.branching[children] += 1;
+            stats.prefix_bytes += node.prefix.len() * atom;
+            stats.heap_bytes += node.prefix.capacity() * atom + node.children.heap_bytes();
+            if node.value.is_some() {
+                stats.keys += 1;
+                stats.key_bytes += key_len * atom;
+                stats.max_depth = stats.max_depth.max(depth);
+                depths += depth;
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .map(|child| (depth + 1, key_len + child.prefix.len(), child)),
+            );
+        }
+        if stats.keys > 0 {
+            stats.avg_depth = depths as f64 / stats.keys as f64;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shape_report() {
+        let mut trie: Trie<u32> = Trie::new();
+        assert!(trie.is_empty());
+        assert_eq!(trie.stats().nodes, 1);
+        assert_eq!(trie.stats().compression_ratio(), 1.0);
+        for word in ["car", "card", "care", "cart", "dog"] {
+            trie.set(word, 0);
+        }
+        trie.set("car", 1);
+        assert_eq!(trie.len(), 5);
+        let stats = trie.stats();
+        // root -> car -> d, e, t and root -> dog
+        assert_eq!(stats.keys, 5);
+        assert_eq!(stats.nodes, 6);
+        assert_eq!(stats.nodes, trie.size());
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.avg_depth, 8.0 / 5.0);
+        assert_eq!(stats.branching, vec![4, 0, 1, 1]);
+        assert_eq!(stats.prefix_bytes, 9);
+        assert_eq!(stats.key_bytes, 18);
+        assert_eq!(stats.compression_ratio(), 0.5);
+        assert!(stats.heap_bytes > stats.prefix_bytes);
+        assert_eq!(trie.remove("cart").ok(), Some(0));
+        assert_eq!(trie.len(), 4);
+        assert_eq!(trie.stats().keys, 4);
+        *trie.entry("do").or_insert(3) += 1;
+        assert_eq!(trie.len(), 5);
+    }
+}
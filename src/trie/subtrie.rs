@@ -0,0 +1,328 @@
+//! views of the keys below a prefix
+//!
+//! a view holds the topmost node all of its keys lead through, and how far
+//! into that node's prefix the view's prefix ends. the rest of that node's
+//! prefix, the stub, starts every key of the view, so keys relative to the
+//! view are matched against the stub and then looked up below the node,
+//! without the view's prefix ever being put in front of them.
+
+use std::iter::FusedIterator;
+
+use super::{
+    common_prefix_len, unit_floor, Iteration, MutableIteration, Trie, TrieAtom, TrieKey, TrieNode,
+};
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// gets a view of the keys starting with prefix, relative to it
+    ///
+    /// returns None if no key starts with prefix.
+    pub fn subtrie(&self, prefix: &K::Slice) -> Option<SubTrie<'_, K, V>> {
+        let prefix = K::atoms(prefix);
+        let (depth, node) = self.root.cover(prefix)?;
+        if node.value.is_none() && node.children.len() == 0 {
+            return None;
+        }
+        Some(SubTrie {
+            node,
+            skip: prefix.len() - depth,
+        })
+    }
+
+    /// gets a mutable view of the keys starting with prefix, relative to it
+    ///
+    /// returns None if no key starts with prefix.
+    pub fn subtrie_mut(&mut self, prefix: &K::Slice) -> Option<SubTrieMut<'_, K, V>> {
+        let prefix = K::atoms(prefix);
+        let Trie { root, len } = self;
+        let (depth, node) = root.cover_mut(prefix)?;
+        if node.value.is_none() && node.children.len() == 0 {
+            return None;
+        }
+        Some(SubTrieMut {
+            node,
+            skip: prefix.len() - depth,
+            len,
+        })
+    }
+}
+
+/// the keys of a trie starting with a prefix, with the prefix cut off
+pub struct SubTrie<'a, K: TrieKey, V> {
+    node: &'a TrieNode<K::Atom, V>,
+    /// length of the part of node's prefix that belongs to the view's prefix
+    skip: usize,
+}
+
+/// the keys of a trie starting with a prefix, with the prefix cut off,
+/// and values as mutable
+///
+/// new keys can be set, removal goes through the trie.
+pub struct SubTrieMut<'a, K: TrieKey, V> {
+    node: &'a mut TrieNode<K::Atom, V>,
+    skip: usize,
+    /// key count of the whole trie
+    len: &'a mut usize,
+}
+
+impl<'a, K: TrieKey, V> SubTrie<'a, K, V> {
+    /// gets the value of a key relative to the prefix
+    pub fn get(&self, key: &K::Slice) -> Option<&'a V> {
+        get(self.node, self.skip, K::atoms(key))
+    }
+
+    /// checks if a key relative to the prefix exists
+    pub fn has(&self, key: &K::Slice) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// gets the number of keys starting with the prefix
+    ///
+    /// walks the subtree, the trie only keeps its total.
+    pub fn len(&self) -> usize {
+        self.node.count()
+    }
+
+    /// checks if there are no keys starting with the prefix
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// iterates over key/value pairs relative to the prefix,
+    /// in lexicographic key order
+    pub fn iter(&self) -> SubIteration<'a, K, V> {
+        iter(self.node, self.skip)
+    }
+}
+
+impl<K: TrieKey, V> SubTrieMut<'_, K, V> {
+    /// gets the value of a key relative to the prefix
+    pub fn get(&self, key: &K::Slice) -> Option<&V> {
+        get(self.node, self.skip, K::atoms(key))
+    }
+
+    /// gets the value of a key relative to the prefix as mutable
+    pub fn get_mut(&mut self, key: &K::Slice) -> Option<&mut V> {
+        let rest = K::atoms(key).strip_prefix(&self.node.prefix[self.skip..])?;
+        if rest.is_empty() {
+            return self.node.value.as_mut();
+        }
+        self.node.leaf_mut(rest)?.get_mut(rest)
+    }
+
+    /// checks if a key relative to the prefix exists
+    pub fn has(&self, key: &K::Slice) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// sets a key relative to the prefix to a value
+    /// returns the key evicted if there was already a key.
+    pub fn set(&mut self, key: &K::Slice, val: V) -> Option<V> {
+        let key = K::atoms(key);
+        let stub = &self.node.prefix[self.skip..];
+        let common = common_prefix_len(stub, key);
+        let slot = if common == stub.len() {
+            self.node.slot_below::<K>(&key[common..])
+        } else {
+            // key parts from the stub, split our node where the last unit
+            // they share ends. the split stays within the stub, and the view's
+            // prefix ends on a unit boundary, so the node still covers the prefix.
+            let common = unit_floor::<K>(key, common);
+            self.node.split(self.skip + common);
+            if common == key.len() {
+                &mut self.node.value
+            } else {
+                self.node.inject(&key[common..])
+            }
+        };
+        let old = slot.replace(val);
+        if old.is_none() {
+            *self.len += 1;
+        }
+        old
+    }
+
+    /// gets the number of keys starting with the prefix
+    ///
+    /// walks the subtree, the trie only keeps its total.
+    pub fn len(&self) -> usize {
+        self.node.count()
+    }
+
+    /// checks if there are no keys starting with the prefix
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// iterates over key/value pairs relative to the prefix,
+    /// in lexicographic key order
+    pub fn iter(&self) -> SubIteration<'_, K, V> {
+        iter(self.node, self.skip)
+    }
+
+    /// iterates over key/value pairs relative to the prefix,
+    /// in lexicographic key order, with values as mutable
+    pub fn iter_mut(&mut self) -> SubIterationMut<'_, K, V> {
+        let TrieNode {
+            prefix,
+            children,
+            value,
+        } = &mut *self.node;
+        let stub = prefix[self.skip..].to_vec();
+        SubIterationMut {
+            head: value.as_mut(),
+            rest: MutableIteration {
+                stack: children
+                    .iter_mut()
+                    .rev()
+                    .map(|child| (stub.len(), child))
+                    .collect(),
+                key: stub,
+            },
+        }
+    }
+}
+
+fn get<'a, A: TrieAtom, V>(node: &'a TrieNode<A, V>, skip: usize, key: &[A]) -> Option<&'a V> {
+    let rest = key.strip_prefix(&node.prefix[skip..])?;
+    if rest.is_empty() {
+        return node.value.as_ref();
+    }
+    node.leaf(rest)?.get(rest)
+}
+
+fn iter<K: TrieKey, V>(node: &TrieNode<K::Atom, V>, skip: usize) -> SubIteration<'_, K, V> {
+    let stub = node.prefix[skip..].to_vec();
+    SubIteration {
+        head: node.value.as_ref(),
+        rest: Iteration {
+            stack: node
+                .children
+                .iter()
+                .rev()
+                .map(|child| (stub.len(), child))
+                .collect(),
+            key: stub,
+        },
+    }
+}
+
+/// key ordered iteration over a view, with keys relative to its prefix
+pub struct SubIteration<'a, K: TrieKey, V> {
+    /// value of the stub itself, comes first
+    head: Option<&'a V>,
+    /// the nodes below, starting out with the stub as key
+    rest: Iteration<'a, K, V>,
+}
+
+/// key ordered iteration over a view, with keys relative to its prefix,
+/// and values as mutable
+pub struct SubIterationMut<'a, K: TrieKey, V> {
+    head: Option<&'a mut V>,
+    rest: MutableIteration<'a, K, V>,
+}
+
+impl<'a, K: TrieKey, V> Iterator for SubIteration<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.head.take() {
+            Some(value) => Some((K::from_atoms(&self.rest.key), value)),
+            None => self.rest.next(),
+        }
+    }
+}
+
+impl<'a, K: TrieKey, V> Iterator for SubIterationMut<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.head.take() {
+            Some(value) => Some((K::from_atoms(&self.rest.key), value)),
+            None => self.rest.next(),
+        }
+    }
+}
+
+impl<K: TrieKey, V> FusedIterator for SubIteration<'_, K, V> {}
+
+impl<K: TrieKey, V> FusedIterator for SubIterationMut<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::words;
+
+    #[test]
+    fn relative_access() {
+        let trie = words(&["app/", "app/config", "app/cache", "apple", "bin/ls", ""]);
+        assert!(trie.subtrie("lib/").is_none());
+        assert!(trie.subtrie("app/configs").is_none());
+
+        let app = trie.subtrie("app/").unwrap();
+        assert_eq!(app.len(), 3);
+        assert_eq!(app.get(""), Some(&0));
+        assert_eq!(app.get("config"), Some(&1));
+        assert_eq!(app.get("cache"), Some(&2));
+        assert_eq!(app.get("conf"), None);
+        assert!(!app.has("le"));
+        let keys: Vec<String> = app.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["", "cache", "config"]);
+
+        // the prefix ends inside a node's prefix
+        let bin = trie.subtrie("bi").unwrap();
+        assert_eq!(bin.get("n/ls"), Some(&4));
+        assert_eq!(bin.get("n/"), None);
+        assert_eq!(bin.get("x"), None);
+        let keys: Vec<String> = bin.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["n/ls"]);
+
+        let all = trie.subtrie("").unwrap();
+        assert_eq!(all.len(), trie.len());
+        assert!(all.iter().map(|(key, _)| key).eq(trie.keys()));
+        assert!(Trie::<usize>::new().subtrie("").is_none());
+    }
+
+    #[test]
+    fn relative_updates() {
+        let mut trie = words(&["app/config", "app/cache", "bin/ls"]);
+        let mut app = trie.subtrie_mut("app/").unwrap();
+        assert_eq!(app.set("config", 10), Some(0));
+        // parts from the stub "c", the covering node is split
+        assert_eq!(app.set("data", 11), None);
+        assert_eq!(app.set("", 12), None);
+        assert_eq!(app.set("cache/tmp", 13), None);
+        *app.get_mut("cache").unwrap() += 20;
+        for (_, value) in app.iter_mut() {
+            *value += 100;
+        }
+        assert_eq!(app.len(), 5);
+        assert_eq!(app.get("data"), Some(&111));
+        assert_eq!(trie.len(), 6);
+        assert_eq!(trie.get("app/"), Some(&112));
+        assert_eq!(trie.get("app/cache"), Some(&121));
+        assert_eq!(trie.get("app/cache/tmp"), Some(&113));
+        assert_eq!(trie.get("app/config"), Some(&110));
+        assert_eq!(trie.get("bin/ls"), Some(&2));
+        assert_eq!(trie.validate(), Ok(()));
+
+        // prefix ending inside a node, keys parting right where it ends
+        let mut trie = words(&["bin/ls", "bin/lsblk"]);
+        let mut bin = trie.subtrie_mut("bin").unwrap();
+        assert_eq!(bin.set("", 5), None);
+        assert_eq!(bin.set("/cat", 6), None);
+        assert_eq!(bin.set("/l", 7), None);
+        let keys: Vec<String> = bin.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["", "/cat", "/l", "/ls", "/lsblk"]);
+        assert_eq!(trie.len(), 5);
+        assert_eq!(trie.validate(), Ok(()));
+
+        // keys parting from the stub inside of a character split before it
+        let mut trie = words(&["tag/café"]);
+        let mut tag = trie.subtrie_mut("tag/").unwrap();
+        assert_eq!(tag.set("cafè", 1), None);
+        assert_eq!(tag.set("caf", 2), None);
+        let keys: Vec<String> = tag.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["caf", "cafè", "café"]);
+        assert_eq!(trie.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,234 @@
+//! substring search over the keys of a trie
+//!
+//! next to the keys themselves, every suffix of every key is kept in a
+//! second trie, pointing back at the keys it is a suffix of. a key contains
+//! a substring when one of its suffixes starts with it, so the keys
+//! containing a substring are the owners of the suffixes below it.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::{Entry, KeyNotFoundError, Trie, TrieKey};
+
+/// a trie that also finds keys by any part of them
+///
+/// suffixes take memory quadratic in key length at worst, each key is
+/// stored once and shared by all of its suffixes.
+#[derive(Debug)]
+pub struct SuffixTrie<K: TrieKey, V> {
+    keys: Trie<V, K>,
+    /// every suffix, including the empty one, with the keys ending in it
+    suffixes: Trie<Owners<K::Atom>, Vec<K::Atom>>,
+}
+
+/// the keys ending in a suffix, each shared by all of its suffixes
+type Owners<A> = BTreeSet<Arc<[A]>>;
+
+impl<K: TrieKey, V> SuffixTrie<K, V> {
+    /// constructs an empty suffix trie
+    pub fn new() -> Self {
+        SuffixTrie {
+            keys: Trie::empty(),
+            suffixes: Trie::empty(),
+        }
+    }
+
+    /// gets the value of a key
+    #[inline]
+    pub fn get(&self, key: &K::Slice) -> Option<&V> {
+        self.keys.get(key)
+    }
+
+    /// gets the value of a key as mutable
+    #[inline]
+    pub fn get_mut(&mut self, key: &K::Slice) -> Option<&mut V> {
+        self.keys.get_mut(key)
+    }
+
+    /// checks if a key exists
+    #[inline]
+    pub fn has(&self, key: &K::Slice) -> bool {
+        self.keys.has(key)
+    }
+
+    /// sets a key to a value
+    /// returns the key evicted if there was already a key.
+    pub fn set(&mut self, key: &K::Slice, val: V) -> Option<V> {
+        let old = self.keys.set(key, val);
+        if old.is_none() {
+            self.index(K::atoms(key));
+        }
+        old
+    }
+
+    /// removes a key, returns its value
+    pub fn remove(&mut self, key: &K::Slice) -> Result<V, KeyNotFoundError<K>> {
+        let value = self.keys.remove(key)?;
+        self.unindex(K::atoms(key));
+        Ok(value)
+    }
+
+    /// gets the number of keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// checks if there are no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// gets the keys containing part, with their values, in lexicographic key order
+    pub fn contains(&self, part: &K::Slice) -> Vec<(K, &V)> {
+        let found: BTreeSet<&[K::Atom]> = self
+            .suffixes
+            .iter_prefix(K::atoms(part))
+            .flat_map(|(_, owners)| owners.iter().map(|owner| &owner[..]))
+            .collect();
+        found
+            .into_iter()
+            .map(|key| (K::from_atoms(key), self.keys.root.get(key).unwrap()))
+            .collect()
+    }
+
+    /// the keys and values, to read them as a plain trie
+    #[inline]
+    pub fn trie(&self) -> &Trie<V, K> {
+        &self.keys
+    }
+
+    /// drops the suffixes, keeping the keys and values
+    pub fn into_trie(self) -> Trie<V, K> {
+        self.keys
+    }
+
+    fn index(&mut self, key: &[K::Atom]) {
+        let owner: Arc<[K::Atom]> = key.into();
+        for start in starts::<K>(key) {
+            let owners = self.suffixes.entry(&key[start..]).or_default();
+            owners.insert(owner.clone());
+        }
+    }
+
+    fn unindex(&mut self, key: &[K::Atom]) {
+        for start in starts::<K>(key) {
+            let Entry::Occupied(mut owners) = self.suffixes.entry(&key[start..]) else {
+                unreachable!("suffixes of a key are indexed");
+            };
+            owners.get_mut().remove(key);
+            if owners.get().is_empty() {
+                owners.remove();
+            }
+        }
+    }
+}
+
+/// where the suffixes of key start, at every unit and at the end
+fn starts<K: TrieKey>(key: &[K::Atom]) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(Some(0), |&start| {
+        (start < key.len()).then(|| start + K::unit_len(&key[start]))
+    })
+}
+
+impl<K: TrieKey, V> Default for SuffixTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: TrieKey, V> From<Trie<V, K>> for SuffixTrie<K, V> {
+    fn from(keys: Trie<V, K>) -> Self {
+        let mut trie = Self::new();
+        let atoms: Vec<Vec<K::Atom>> = keys
+            .iter()
+            .map(|(key, _)| K::atoms(key.borrow()).to_vec())
+            .collect();
+        for key in atoms {
+            trie.index(&key);
+        }
+        trie.keys = keys;
+        trie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::Random;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn finds_substrings() {
+        let mut trie: SuffixTrie<String, usize> = SuffixTrie::new();
+        for (i, word) in ["banana", "bandana", "cabana", "ant", "", "日本語"]
+            .iter()
+            .enumerate()
+        {
+            trie.set(word, i);
+        }
+        let found = |trie: &SuffixTrie<String, usize>, part: &str| -> Vec<String> {
+            trie.contains(part)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+        assert_eq!(found(&trie, "ana"), vec!["banana", "bandana", "cabana"]);
+        assert_eq!(
+            found(&trie, "an"),
+            vec!["ant", "banana", "bandana", "cabana"]
+        );
+        assert_eq!(found(&trie, "nd"), vec!["bandana"]);
+        assert_eq!(found(&trie, "本"), vec!["日本語"]);
+        assert_eq!(found(&trie, "xyz"), Vec::<String>::new());
+        assert_eq!(found(&trie, "").len(), 6);
+        assert_eq!(trie.contains("cab"), vec![("cabana".to_string(), &2)]);
+
+        // one copy of a key, shared by each of its suffixes
+        let owners = trie.suffixes.get(b"bana".as_slice()).unwrap();
+        assert_eq!(owners.len(), 1);
+        let owner = owners.first().unwrap();
+        assert_eq!(Arc::strong_count(owner), "cabana".len() + 1);
+        // suffixes start on characters only
+        assert_eq!(trie.suffixes.iter_prefix(&[0x9c]).count(), 0);
+        let owner = trie.suffixes.get("語".as_bytes()).unwrap().first().unwrap();
+        assert_eq!(Arc::strong_count(owner), "日本語".chars().count() + 1);
+
+        trie.set("banana", 10);
+        assert_eq!(trie.contains("nan"), vec![("banana".to_string(), &10)]);
+        assert_eq!(trie.remove("banana"), Ok(10));
+        assert_eq!(found(&trie, "ana"), vec!["bandana", "cabana"]);
+        assert!(trie.remove("banana").is_err());
+        assert_eq!(trie.len(), 5);
+    }
+
+    #[test]
+    fn stays_in_sync() {
+        let mut random = Random(0x9e3779b97f4a7c15);
+        let mut next = |below: u64| random.next(below);
+        let mut trie: SuffixTrie<Vec<u8>, usize> = SuffixTrie::new();
+        let mut model = BTreeMap::new();
+        for step in 0..2000 {
+            let len = next(6) as usize;
+            let key: Vec<u8> = (0..len).map(|_| next(3) as u8).collect();
+            if next(3) == 0 {
+                assert_eq!(trie.remove(&key).ok(), model.remove(&key));
+            } else {
+                assert_eq!(trie.set(&key, step), model.insert(key, step));
+            }
+            let part: Vec<u8> = (0..next(3)).map(|_| next(3) as u8).collect();
+            let expected: Vec<(Vec<u8>, &usize)> = model
+                .iter()
+                .filter(|(key, _)| {
+                    part.is_empty() || key.windows(part.len()).any(|window| window == part)
+                })
+                .map(|(key, value)| (key.clone(), value))
+                .collect();
+            assert_eq!(trie.contains(&part), expected);
+        }
+        let rebuilt = SuffixTrie::from(trie.into_trie());
+        assert_eq!(rebuilt.contains(&[]).len(), model.len());
+        assert_eq!(rebuilt.suffixes.validate(), Ok(()));
+    }
+}
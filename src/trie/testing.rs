@@ -0,0 +1,34 @@
+//! helpers shared by the tests of the trie modules
+
+use super::Trie;
+
+/// xorshift, good enough to pick keys and operations
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    /// gets a number below below
+    pub(crate) fn next(&mut self, below: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % below
+    }
+}
+
+/// a trie of words, each valued by its position
+pub(crate) fn words(words: &[&str]) -> Trie<usize> {
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| (word.to_string(), i))
+        .collect()
+}
+
+/// collects pairs with borrowed values into owned ones
+pub(crate) fn pairs<'a, K, V: Clone + 'a>(
+    iter: impl IntoIterator<Item = (K, &'a V)>,
+) -> Vec<(K, V)> {
+    iter.into_iter()
+        .map(|(key, value)| (key, value.clone()))
+        .collect()
+}
@@ -0,0 +1,245 @@
+//! structural invariants
+//!
+//! a compressed tree has exactly one shape for a set of keys. every node
+//! below the root has a prefix made of whole units, and holds a value or
+//! branches, siblings differ in their first unit and are kept in order.
+
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+
+use super::{first_unit, unit_floor, Trie, TrieAtom, TrieKey, TrieNode};
+
+/// a broken structural invariant, found by [`Trie::validate`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantError<A> {
+    /// a node below the root has an empty prefix
+    EmptyPrefix { path: Vec<A> },
+    /// a node below the root has no value and less than two children
+    Uncompressed { path: Vec<A> },
+    /// the children of a node are not strictly ordered by first unit,
+    /// or can not be found by it
+    Siblings { path: Vec<A> },
+    /// a node's prefix ends inside of a unit, like a character of a string
+    Boundary { path: Vec<A> },
+    /// the key count is off from the number of values
+    Len { len: usize, counted: usize },
+}
+
+impl<A: Debug> Display for InvariantError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::EmptyPrefix { path } => write!(f, "empty prefix at {path:?}"),
+            InvariantError::Uncompressed { path } => {
+                write!(f, "valueless node without branches at {path:?}")
+            }
+            InvariantError::Siblings { path } => write!(f, "misordered children at {path:?}"),
+            InvariantError::Boundary { path } => write!(f, "prefix splits a unit at {path:?}"),
+            InvariantError::Len { len, counted } => {
+                write!(f, "length is {len}, but {counted} keys were found")
+            }
+        }
+    }
+}
+
+impl<A: Debug> Error for InvariantError<A> {}
+
+impl<K: TrieKey, V> Trie<V, K> {
+    /// checks the structural invariants of the tree
+    ///
+    /// this walks every node, it is meant for tests and debugging.
+    pub fn validate(&self) -> Result<(), InvariantError<K::Atom>> {
+        let mut path = Vec::new();
+        let counted = self.root.validate::<K>(&mut path, true)?;
+        if counted != self.len {
+            return Err(InvariantError::Len {
+                len: self.len,
+                counted,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<A: TrieAtom, V> TrieNode<A, V> {
+    /// checks this node and the ones below, path is the key leading up to us
+    ///
+    /// returns the number of values found.
+    fn validate<K: TrieKey<Atom = A>>(
+        &self,
+        path: &mut Vec<A>,
+        root: bool,
+    ) -> Result<usize, InvariantError<A>> {
+        let depth = path.len();
+        path.extend_from_slice(&self.prefix);
+        if !root && self.prefix.is_empty() {
+            return Err(InvariantError::EmptyPrefix { path: path.clone() });
+        }
+        if !root && self.value.is_none() && self.children.len() < 2 {
+            return Err(InvariantError::Uncompressed { path: path.clone() });
+        }
+        if unit_floor::<K>(&self.prefix, self.prefix.len()) != self.prefix.len() {
+            return Err(InvariantError::Boundary { path: path.clone() });
+        }
+        let mut counted = usize::from(self.value.is_some());
+        let mut previous: Option<&[A]> = None;
+        for child in self.children.iter() {
+            if child.prefix.is_empty() {
+                return Err(InvariantError::EmptyPrefix { path: path.clone() });
+            }
+            let unit = first_unit::<K>(&child.prefix);
+            let ordered = previous.is_none_or(|previous| previous < unit);
+            let found = self
+                .children
+                .get(unit)
+                .is_some_and(|found| std::ptr::eq(found, child));
+            if !ordered || !found {
+                return Err(InvariantError::Siblings { path: path.clone() });
+            }
+            previous = Some(unit);
+            counted += child.validate::<K>(path, false)?;
+        }
+        path.truncate(depth);
+        Ok(counted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::testing::Random;
+    use crate::trie::Entry;
+    use std::collections::BTreeMap;
+
+    /// runs random operations on a trie and a map, checking they agree
+    fn differential<K, F>(seed: u64, steps: usize, mut key: F)
+    where
+        K: TrieKey + Ord + Clone + Debug,
+        F: FnMut(&mut Random) -> K,
+    {
+        let mut random = Random(seed);
+        let mut trie: Trie<u64, K> = Trie::empty();
+        let mut model: BTreeMap<K, u64> = BTreeMap::new();
+        for step in 0..steps as u64 {
+            let key = key(&mut random);
+            let slice: &K::Slice = key.borrow();
+            match random.next(7) {
+                0 | 1 => assert_eq!(trie.set(slice, step), model.insert(key.clone(), step)),
+                2 | 3 => assert_eq!(trie.remove(slice).ok(), model.remove::<K>(&key)),
+                4 => {
+                    let value = *trie.entry(slice).or_insert(step);
+                    assert_eq!(value, *model.entry(key.clone()).or_insert(step));
+                }
+                5 => {
+                    let atoms = K::atoms(slice);
+                    let removed: Vec<(K, u64)> = trie.remove_prefix(slice).into_iter().collect();
+                    let under = |k: &K| K::atoms(k.borrow()).starts_with(atoms);
+                    let expected: Vec<(K, u64)> = model
+                        .iter()
+                        .filter(|(k, _)| under(k))
+                        .map(|(k, v)| (k.clone(), *v))
+                        .collect();
+                    model.retain(|k, _| !under(k));
+                    assert_eq!(removed, expected);
+                }
+                _ => {
+                    let removed = match trie.entry(slice) {
+                        Entry::Occupied(entry) => Some(entry.remove()),
+                        Entry::Vacant(_) => None,
+                    };
+                    assert_eq!(removed, model.remove::<K>(&key));
+                }
+            }
+            assert_eq!(trie.validate(), Ok(()), "seed {seed} step {step}");
+            assert_eq!(trie.get(key.borrow()), model.get::<K>(&key));
+            assert_eq!(trie.len(), model.len());
+            if step % 64 == 0 {
+                assert!(trie.iter().map(|(k, v)| (k, *v)).eq(model.clone()));
+            }
+        }
+        assert!(trie.into_iter().eq(model));
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        for seed in 1..=8 {
+            // few atoms, short keys, so keys keep splitting and merging nodes
+            differential(seed * 0x9e37_79b9, 2_000, |random| {
+                let len = random.next(6) as usize;
+                (0..len)
+                    .map(|_| char::from(b'a' + random.next(3) as u8))
+                    .collect::<String>()
+            });
+        }
+    }
+
+    #[test]
+    fn matches_btree_map_wide() {
+        for seed in 1..=4 {
+            // many first atoms, so child layouts grow and shrink
+            differential(seed * 0x2545_f491, 4_000, |random| {
+                let len = 1 + random.next(3) as usize;
+                (0..len)
+                    .map(|i| match i {
+                        0 => random.next(256) as u8,
+                        _ => random.next(4) as u8,
+                    })
+                    .collect::<Vec<u8>>()
+            });
+        }
+    }
+
+    #[test]
+    fn matches_btree_map_unicode() {
+        // pieces sharing leading bytes, so siblings share their first byte
+        let pieces = [
+            "a", "é", "è", "ê", "\u{301}", "日", "本", "曜", "👍", "🏽", "\u{200d}", "🇯",
+        ];
+        for seed in 1..=4 {
+            differential(seed * 0x6c07_8965, 2_000, |random| {
+                let len = random.next(4) as usize;
+                (0..len)
+                    .map(|_| pieces[random.next(pieces.len() as u64) as usize])
+                    .collect::<String>()
+            });
+        }
+    }
+
+    #[test]
+    fn reports_broken_trees() {
+        let mut trie: Trie<u32> = Trie::new();
+        trie.set("ab", 1);
+        trie.set("ac", 2);
+        assert_eq!(trie.validate(), Ok(()));
+        trie.root.children.get_mut(b"a").unwrap().value = None;
+        trie.root
+            .children
+            .get_mut(b"a")
+            .unwrap()
+            .children
+            .remove(b"c");
+        assert_eq!(
+            trie.validate(),
+            Err(InvariantError::Uncompressed {
+                path: b"a".to_vec()
+            })
+        );
+        // a node ending inside of a character
+        let mut trie: Trie<u32> = Trie::new();
+        trie.set("é", 1);
+        let node = trie.root.children.get_mut("é".as_bytes()).unwrap();
+        node.split(1);
+        node.value = Some(2);
+        trie.len = 2;
+        assert_eq!(
+            trie.validate(),
+            Err(InvariantError::Boundary { path: vec![0xc3] })
+        );
+        let mut trie: Trie<u32> = Trie::new();
+        trie.set("ab", 1);
+        trie.len = 2;
+        assert_eq!(
+            trie.validate(),
+            Err(InvariantError::Len { len: 2, counted: 1 })
+        );
+    }
+}